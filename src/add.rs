@@ -1,85 +1,429 @@
 use crate::error::*;
-use crate::manifest::add_pallet_to_manifest;
-use crate::registry::registry_path;
-use crate::runtime::add_pallet_to_runtime;
+use crate::features::validate_features;
+use crate::genesis::add_pallet_to_chain_spec;
+use crate::manifest::{
+    add_pallet_to_manifest, dependency_key, find_dependency_key, find_dependency_package,
+    set_dependency_features, PalletDependencyOptions,
+};
+use crate::metadata::pallet_metadata;
+use crate::output::{message, status};
+use crate::registry::{
+    crate_dependencies, crate_features, crate_versions, registry_path, update_registry_index,
+};
+use crate::rpc::{add_pallet_rpc, add_pallet_runtime_api};
+use crate::runtime::{add_pallet_benchmark, add_pallet_to_runtime, set_pallet_types};
+use crate::spec::PalletSpec;
+use crate::transaction::{transaction, FileChange, Snapshot};
+use crate::workspace::read_manifest;
 
-use cargo_edit::{get_latest_dependency, registry_url, update_registry_index};
-use log::{debug, info};
-use std::path::PathBuf;
+use cargo_edit::{get_latest_dependency, registry_url, Dependency, ErrorKind};
+use log::debug;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
 use url::Url;
 
+/// A pallet added to the runtime.
+#[derive(Clone, Debug, Serialize)]
+pub struct AddedPallet {
+    /// Name of the pallet crate.
+    pub name: String,
+    /// Version of the pallet crate, unless it is a local crate without a version of its own.
+    pub version: Option<String>,
+    /// Registry of the pallet crate, or `None` for local crates.
+    pub registry: Option<String>,
+    /// Directory of the local pallet crate, if any.
+    pub path: Option<PathBuf>,
+    /// The runtime & node files changed to add the pallet, with the code added to them.
+    pub files: Vec<FileChange>,
+}
+
+/// Add the `pallets` to the runtime whose manifest is `manifest_path`, resolving them in the
+/// `registry` (crates.io by default), and restore the runtime & node files if any can't be added.
 pub fn execute_add(
-    manifest_path: &PathBuf,
-    pallet: &str,
-    alias: Option<&str>,
+    manifest_path: &Path,
+    pallets: &[PalletSpec],
     registry: Option<&str>,
-) -> CliResult<()> {
+) -> CliResult<Vec<AddedPallet>> {
     debug!("Manifest path: {:?}", manifest_path);
-    debug!("Pallet: {}", pallet);
-    debug!("Alias: {:?}", alias);
+    debug!("Pallets: {:?}", pallets);
     debug!("Registry: {:?}", registry);
 
+    // Restore the runtime & node files if any of the pallets can't be added
+    let added = transaction(manifest_path, || {
+        add_pallets(manifest_path, pallets, registry)
+    })?;
+
+    if pallets.len() > 1 {
+        message(format!(
+            "Added {} pallets to your node runtime.",
+            pallets.len()
+        ));
+    }
+
+    Ok(added)
+}
+
+/// Add the `pallets` to the runtime, after the pallets they depend on, in the order they are
+/// added.
+pub fn add_pallets(
+    manifest_path: &Path,
+    pallets: &[PalletSpec],
+    registry: Option<&str>,
+) -> CliResult<Vec<AddedPallet>> {
     // Lookup registry URL
-    let reg_url = registry_url(manifest_path.as_ref(), registry)
-        .map_err(|e| CliError::Registry(e.to_string()))?;
+    let reg_url =
+        registry_url(manifest_path, registry).map_err(|e| CliError::Registry(e.to_string()))?;
     debug!("Registry URL: {}", reg_url);
 
     // Lookup registry path
-    let reg_path = registry_path(manifest_path.as_ref(), registry)
-        .map_err(|e| CliError::Registry(e.to_string()))?;
+    let reg_path =
+        registry_path(manifest_path, registry).map_err(|e| CliError::Registry(e.to_string()))?;
     debug!("Registry path: {:?}", reg_path);
 
-    info!(
+    message(format!(
         "Using registry '{}' at: {}",
         registry.unwrap_or("crates-io"),
         reg_url
-    );
+    ));
 
-    // Update registry index
+    // Update registry index, once for all the pallets, unless they are all local crates
     //TODO: add offline flag and skip update if set
-    update_registry_index(&reg_url).map_err(|e| CliError::Registry(e.to_string()))?;
+    if pallets.iter().any(|spec| spec.path.is_none()) {
+        update_registry_index(&reg_url).map_err(|e| {
+            CliError::Registry(e.to_string()).context(format!(
+                "Could not update the index of registry {}.",
+                registry.unwrap_or("crates-io")
+            ))
+        })?;
+    }
 
-    // Add pallet dependency (and related dependencies, recursively)
-    add_pallet_dependency(
-        manifest_path,
-        pallet,
-        alias,
-        (registry, &reg_url, &reg_path),
-    )?;
+    // Resolve all the pallets before updating anything
+    let dependencies = pallets
+        .iter()
+        .map(|spec| resolve_pallet(manifest_path, spec, (registry, &reg_url)))
+        .collect::<CliResult<Vec<_>>>()?;
 
-    Ok(())
+    // Add the pallets after the pallets they depend on
+    let order = dependency_order(&dependencies, &reg_url)?;
+    debug!("Pallets order: {:?}", order);
+    let mut added = Vec::with_capacity(order.len());
+    for i in order {
+        let (dependency, spec) = (&dependencies[i], &pallets[i]);
+        let snapshot = Snapshot::take(manifest_path)?;
+
+        // Add pallet dependency (and related dependencies, recursively)
+        add_pallet_dependency(
+            manifest_path,
+            dependency,
+            spec,
+            (registry, &reg_url, &reg_path),
+        )
+        .map_err(|e| e.context(format!("Could not add pallet {}.", spec.pallet)))?;
+
+        added.push(AddedPallet {
+            name: dependency.name.clone(),
+            version: dependency.version().map(str::to_owned),
+            registry: match spec.path {
+                Some(_) => None,
+                None => Some(registry.unwrap_or("crates-io").to_owned()),
+            },
+            path: spec.path.clone(),
+            files: snapshot.changes()?,
+        });
+    }
+
+    Ok(added)
+}
+
+/// Lookup the version of the pallet `spec` to add, its latest version unless it requests one, or
+/// the version of its local crate.
+fn resolve_pallet(
+    manifest_path: &Path,
+    spec: &PalletSpec,
+    (registry, reg_url): (Option<&str>, &Url),
+) -> CliResult<Dependency> {
+    if let Some(path) = &spec.path {
+        return local_pallet(spec, path);
+    }
+
+    let registry = registry.unwrap_or("crates-io").to_owned();
+    let version = match &spec.version {
+        Some(version) => version,
+        None => {
+            return get_latest_dependency(&spec.pallet, true, manifest_path, &Some(reg_url.clone()))
+                .map_err(|e| match e.kind() {
+                    ErrorKind::NoCrate(_) => CliError::PalletNotFound {
+                        name: spec.pallet.clone(),
+                        registry,
+                    },
+                    _ => CliError::Dependency(e.to_string()),
+                })
+        }
+    };
+
+    if let Some(versions) = crate_versions(reg_url, &spec.pallet)? {
+        if !versions.contains(version) {
+            return Err(CliError::PalletVersionNotFound {
+                name: spec.pallet.clone(),
+                version: version.clone(),
+                registry,
+            });
+        }
+    }
+    Ok(Dependency::new(&spec.pallet).set_version(version))
+}
+
+/// Read the name & version of the local pallet crate in the directory `path`, which must be the
+/// crate of the pallet `spec`.
+fn local_pallet(spec: &PalletSpec, path: &Path) -> CliResult<Dependency> {
+    let crate_manifest_path = path.join("Cargo.toml");
+    if !crate_manifest_path.is_file() {
+        return Err(CliError::Manifest(format!(
+            "Could not find a pallet crate in {}.",
+            path.display()
+        )));
+    }
+
+    let manifest = read_manifest(&crate_manifest_path)?;
+    let package = manifest.package().as_ref().ok_or_else(|| {
+        CliError::Manifest(format!(
+            "{} is not a crate manifest.",
+            crate_manifest_path.display()
+        ))
+    })?;
+    if package.name() != spec.pallet {
+        return Err(CliError::Dependency(format!(
+            "The crate in {} is {}, not {}.",
+            path.display(),
+            package.name(),
+            spec.pallet
+        )));
+    }
+
+    // Crates without a version of their own are path only dependencies
+    let dependency = Dependency::new(package.name());
+    let dependency = match package.version() {
+        Some(version) => dependency.set_version(version),
+        None => dependency,
+    };
+    Ok(dependency.set_path(&path.to_string_lossy()))
+}
+
+/// Order the pallets `dependencies` so that each pallet comes after the other pallets it depends
+/// on, keeping the requested order otherwise.
+fn dependency_order(dependencies: &[Dependency], reg_url: &Url) -> CliResult<Vec<usize>> {
+    let crate_deps = dependencies
+        .iter()
+        .map(|dependency| crate_dependencies(reg_url, dependency).map(Option::unwrap_or_default))
+        .collect::<CliResult<Vec<_>>>()?;
+
+    let depends_on = |i: usize, j: usize| {
+        dependencies[i].name != dependencies[j].name
+            && crate_deps[i].contains(&dependencies[j].name)
+    };
+
+    let mut pending = (0..dependencies.len()).collect::<Vec<_>>();
+    let mut order = Vec::with_capacity(pending.len());
+    while !pending.is_empty() {
+        // Fall back to the requested order on dependency cycles
+        let next = pending
+            .iter()
+            .position(|&i| !pending.iter().any(|&j| depends_on(i, j)))
+            .unwrap_or(0);
+        order.push(pending.remove(next));
+    }
+
+    Ok(order)
 }
 
 fn add_pallet_dependency(
-    manifest_path: &PathBuf,
-    pallet: &str,
-    alias: Option<&str>,
+    manifest_path: &Path,
+    dependency: &Dependency,
+    spec: &PalletSpec,
     (registry, reg_url, _reg_path): (Option<&str>, &Url, &PathBuf),
 ) -> CliResult<()> {
-    // Lookup pallet latest version
-    let dependency =
-        get_latest_dependency(pallet, true, manifest_path.as_ref(), &Some(reg_url.clone()))
-            .map_err(|e| CliError::Dependency(e.to_string()))?;
-
     let name = &dependency.name;
-    let version = &dependency.version().unwrap();
-    debug!("Pallet found: {} v{}", name, version);
+    // Local pallets without a version of their own are described by their name only
+    let pallet = match dependency.version() {
+        Some(version) => format!("{} v{}", name, version),
+        None => name.clone(),
+    };
+    debug!("Pallet found: {}", pallet);
+
+    let alias = spec.alias.as_deref();
+    let (instance, index) = (spec.instance, spec.index);
+    let (features, via) = (&spec.features, spec.via.as_deref());
+    let section = &spec.section;
+    let path = spec.path.as_deref();
+
+    // The features of local pallets are read from their manifest
+    let pallet_features = match path {
+        Some(path) => Some(read_manifest(&path.join("Cargo.toml"))?.features()),
+        None => crate_features(reg_url, dependency)?,
+    };
+    debug!("Pallet features: {:?}", pallet_features);
+
+    // Check the alias & the requested features before updating anything
+    validate_alias(manifest_path, name, alias, instance)?;
+    validate_features(dependency, features, pallet_features.clone())?;
+
+    status("Adding", &pallet);
+
+    // Dev & build dependencies e.g. mock pallets for tests aren't part of the runtime code
+    if !section.is_runtime() {
+        let options = PalletDependencyOptions {
+            alias,
+            section: section.clone(),
+            registry,
+            path,
+            enabled_features: features,
+            ..PalletDependencyOptions::default()
+        };
+        add_pallet_to_manifest(manifest_path, dependency, &options)?;
+        message(format!(
+            "Added pallet {} to [{}] in your node runtime manifest.",
+            pallet,
+            section.path().join(".")
+        ));
+        return Ok(());
+    }
 
     // Add pallet default config to runtime's lib.rs
-    add_pallet_to_runtime(manifest_path.as_ref(), &dependency, &alias)?;
+    let metadata = pallet_metadata(dependency, reg_url, path)?;
+    let runtime_pallet = add_pallet_to_runtime(
+        manifest_path,
+        dependency,
+        &alias,
+        instance,
+        index,
+        &metadata,
+    )?;
+
+    // Bind the requested associated types in the pallet trait implementation
+    if !spec.types.is_empty() {
+        set_pallet_types(manifest_path, &runtime_pallet, &spec.types)?;
+    }
 
-    info!(
-        "Added pallet {} v{} as dependency in your node runtime manifest.",
-        name, version
-    );
+    message(format!(
+        "Added pallet {} configuration in your node runtime.",
+        pallet
+    ));
 
     // Add pallet to runtime manifest
-    add_pallet_to_manifest(manifest_path.as_ref(), &dependency, &alias, registry)?;
+    let options = PalletDependencyOptions {
+        alias,
+        instance,
+        section: section.clone(),
+        registry,
+        path,
+        pallet_features: pallet_features.as_deref(),
+        ..PalletDependencyOptions::default()
+    };
+    add_pallet_to_manifest(manifest_path, dependency, &options)?;
+
+    // Enable the requested pallet features
+    if !features.is_empty() {
+        let key = find_dependency_key(manifest_path, name)?
+            .ok_or_else(|| CliError::Manifest(format!("Dependency {} not found.", name)))?;
+        set_dependency_features(manifest_path, &key, features, &[], via)?;
+    }
+
+    message(format!(
+        "Added pallet {} as dependency in your node runtime manifest.",
+        pallet
+    ));
+
+    // Add pallet default genesis config to node's chain_spec.rs
+    if add_pallet_to_chain_spec(manifest_path, &runtime_pallet, &metadata)? {
+        message(format!(
+            "Added pallet {} genesis config in your node chain spec.",
+            pallet
+        ));
+    }
+
+    // Add pallet benchmarks to runtime's lib.rs, if the pallet crate declares them
+    if pallet_features
+        .iter()
+        .flatten()
+        .any(|feature| feature == "runtime-benchmarks")
+        && add_pallet_benchmark(manifest_path, &runtime_pallet)?
+    {
+        message(format!(
+            "Added pallet {} benchmarks in your node runtime.",
+            pallet
+        ));
+    }
+
+    // Add pallet runtime API implementation to runtime's lib.rs & RPC extension to node's rpc.rs
+    if spec.with_rpc {
+        let runtime_api = add_pallet_runtime_api(
+            manifest_path,
+            dependency,
+            &runtime_pallet,
+            &metadata,
+            (registry, reg_url),
+        )?;
+        add_pallet_rpc(
+            manifest_path,
+            dependency,
+            &runtime_pallet,
+            &metadata,
+            runtime_api.as_deref(),
+            (registry, reg_url),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Rust keywords, which can't be used as pallet names in code.
+const KEYWORDS: [&str; 51] = [
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "crate",
+    "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "if", "impl", "in",
+    "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref",
+    "return", "self", "Self", "static", "struct", "super", "trait", "true", "try", "type",
+    "typeof", "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+];
+
+/// Check that `alias` derives valid Rust identifiers for the pallet path in code & its name in
+/// the runtime, and that the dependency key it derives isn't used by another crate.
+fn validate_alias(
+    manifest_path: &Path,
+    package: &str,
+    alias: Option<&str>,
+    instance: Option<u32>,
+) -> CliResult<()> {
+    let alias = match alias {
+        Some(alias) => alias,
+        None => return Ok(()),
+    };
+
+    let key = dependency_key(alias);
+    let valid = alias.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && alias
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        && !key.is_empty()
+        && key != "_"
+        && !KEYWORDS.contains(&key.as_str());
+    if !valid {
+        return Err(CliError::Generic(format!(
+            "Alias {} is not a valid Rust identifier.",
+            alias
+        )));
+    }
 
-    info!(
-        "Added metadata {} v{} configuration in your node runtime.",
-        name, version
-    );
+    // Instances share the pallet dependency, the alias only names the instance in the runtime
+    if instance.is_none() {
+        if let Some(other) = find_dependency_package(manifest_path, &key)? {
+            if other != package {
+                return Err(CliError::Dependency(format!(
+                    "Alias {} is already used by dependency {} of your node runtime.",
+                    alias, other
+                )));
+            }
+        }
+    }
 
     Ok(())
 }
@@ -0,0 +1,84 @@
+use crate::error::{CliError, CliResult, MessageFormat};
+use crate::git::read_file_at_revision;
+use crate::output::message;
+use crate::runtime::{
+    check_runtime_declaration, read_runtime_lib, runtime_lib_path, runtime_pallets, RuntimePallet,
+};
+
+use std::path::Path;
+
+use log::{debug, warn};
+use serde_json::json;
+
+/// Check that the indices of the pallets of the runtime whose manifest is `manifest_path` didn't
+/// change since the git revision `rev`. With the JSON `format`, the issues are written to stdout
+/// as a JSON object rather than logged.
+pub fn execute_check(manifest_path: &Path, rev: &str, format: MessageFormat) -> CliResult<()> {
+    let lib_path = runtime_lib_path(manifest_path);
+    debug!("Runtime: {:?}", lib_path);
+    debug!("Revision: {}", rev);
+
+    let current = runtime_pallets(&read_runtime_lib(manifest_path)?)?;
+    let previous = read_file_at_revision(&lib_path, rev)?;
+    check_runtime_declaration(&lib_path, &previous)?;
+    let previous = runtime_pallets(&previous)?;
+
+    let issues = index_issues(&previous, &current);
+    match format {
+        MessageFormat::Human => {
+            for issue in &issues {
+                warn!("{}", issue);
+            }
+        }
+        MessageFormat::Json => println!(
+            "{}",
+            json!({ "reason": "index-check", "revision": rev, "issues": issues })
+        ),
+    }
+
+    if issues.is_empty() {
+        message(format!(
+            "Pallet indices are consistent with revision {}.",
+            rev
+        ));
+        Ok(())
+    } else {
+        Err(CliError::Generic(format!(
+            "Found {} pallet index issue(s) against revision {}.",
+            issues.len(),
+            rev
+        )))
+    }
+}
+
+/// Describe the index collisions in `current`, and the index changes & reuses since `previous`.
+fn index_issues(previous: &[RuntimePallet], current: &[RuntimePallet]) -> Vec<String> {
+    let mut issues = Vec::new();
+
+    for (i, pallet) in current.iter().enumerate() {
+        if let Some(other) = current[..i].iter().find(|p| p.index == pallet.index) {
+            issues.push(format!(
+                "Pallets {} and {} have the same index {}.",
+                other.name, pallet.name, pallet.index
+            ));
+        }
+
+        match previous.iter().find(|p| p.name == pallet.name) {
+            Some(old) if old.index != pallet.index => issues.push(format!(
+                "Pallet {} index changed from {} to {}.",
+                pallet.name, old.index, pallet.index
+            )),
+            Some(_) => {}
+            None => {
+                if let Some(old) = previous.iter().find(|p| p.index == pallet.index) {
+                    issues.push(format!(
+                        "Pallet {} reuses index {} of pallet {}.",
+                        pallet.name, pallet.index, old.name
+                    ));
+                }
+            }
+        }
+    }
+
+    issues
+}
@@ -0,0 +1,245 @@
+use crate::error::*;
+use crate::export::{runtime_inventory, ExportedPallet, Inventory};
+use crate::git::read_file_at_revision;
+use crate::output::message;
+use crate::runtime::runtime_lib_path;
+use crate::workspace::{find_runtime_manifest, find_workspace_root};
+
+use log::debug;
+use serde_json::{json, Value};
+use std::{
+    env,
+    fmt::{Display, Formatter, Result as FmtResult},
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+};
+
+/// A pallet-level change between two runtimes.
+#[derive(Debug, PartialEq)]
+enum PalletChange {
+    Added(String, ExportedPallet),
+    Removed(String, ExportedPallet),
+    /// Change of the `field` of the pallet `key`, from & to a description of its value.
+    Changed {
+        key: String,
+        field: String,
+        from: String,
+        to: String,
+    },
+}
+
+impl Display for PalletChange {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::Added(key, pallet) => write!(f, "+ {} {}", key, describe(pallet)),
+            Self::Removed(key, pallet) => write!(f, "- {} {}", key, describe(pallet)),
+            Self::Changed {
+                key,
+                field,
+                from,
+                to,
+            } => write!(f, "~ {} {}: {} -> {}", key, field, from, to),
+        }
+    }
+}
+
+impl PalletChange {
+    /// Describe the change as a JSON object, with the pallet for additions & removals.
+    fn to_json(&self) -> Value {
+        match self {
+            Self::Added(key, pallet) => {
+                json!({ "reason": "pallet-change", "change": "added", "key": key, "pallet": pallet })
+            }
+            Self::Removed(key, pallet) => {
+                json!({ "reason": "pallet-change", "change": "removed", "key": key, "pallet": pallet })
+            }
+            Self::Changed {
+                key,
+                field,
+                from,
+                to,
+            } => json!({
+                "reason": "pallet-change",
+                "change": "changed",
+                "key": key,
+                "field": field,
+                "from": from,
+                "to": to,
+            }),
+        }
+    }
+}
+
+/// Write the pallet changes between `old` & `new` to stdout, each a git revision or the path of a
+/// runtime, `new` defaulting to the working tree of the runtime whose manifest is `manifest_path`.
+/// With the JSON `format`, each change is written as a JSON object.
+pub fn execute_diff(
+    manifest_path: &Path,
+    old: &str,
+    new: Option<&str>,
+    runtime: Option<&str>,
+    format: MessageFormat,
+) -> CliResult<()> {
+    debug!("Manifest path: {:?}", manifest_path);
+    debug!("Old: {}", old);
+    debug!("New: {:?}", new);
+
+    let old_inventory = inventory_of(manifest_path, old, runtime)?;
+    let new_inventory = match new {
+        Some(new) => inventory_of(manifest_path, new, runtime)?,
+        None => runtime_inventory(manifest_path)?,
+    };
+
+    let changes = inventory_changes(&old_inventory, &new_inventory);
+    if changes.is_empty() {
+        message(format!(
+            "No pallet changes between {} and {}.",
+            old,
+            new.unwrap_or("the working tree")
+        ));
+        return Ok(());
+    }
+
+    let mut output = String::new();
+    for change in &changes {
+        match format {
+            MessageFormat::Human => output.push_str(&format!("{}\n", change)),
+            MessageFormat::Json => output.push_str(&format!("{}\n", change.to_json())),
+        }
+    }
+    io::stdout().write_all(output.as_bytes())?;
+
+    Ok(())
+}
+
+/// Read the inventory of the runtime at `source`: the path of a runtime manifest or crate
+/// directory, or a git revision of the runtime whose manifest is `manifest_path`.
+fn inventory_of(manifest_path: &Path, source: &str, runtime: Option<&str>) -> CliResult<Inventory> {
+    let path = Path::new(source);
+    if path.exists() {
+        let path = if path.is_dir() {
+            path.join("Cargo.toml")
+        } else {
+            path.to_path_buf()
+        };
+        return runtime_inventory(&find_runtime_manifest(&path, runtime)?);
+    }
+
+    inventory_at_revision(manifest_path, source)
+}
+
+/// Read the inventory of the runtime whose manifest is `manifest_path` at the git revision
+/// `rev`, from a copy of its manifest, `lib.rs` & workspace manifest at that revision.
+fn inventory_at_revision(manifest_path: &Path, rev: &str) -> CliResult<Inventory> {
+    let manifest_path = manifest_path.canonicalize()?;
+    let root_path = find_workspace_root(&manifest_path)?;
+    let base = root_path
+        .as_deref()
+        .unwrap_or(&manifest_path)
+        .parent()
+        .unwrap_or_else(|| Path::new("/"))
+        .to_path_buf();
+
+    let dir = env::temp_dir().join(format!(
+        "substrate-deps-{}-{}",
+        process::id(),
+        rev.replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+    ));
+    debug!("Reading revision {} in: {:?}", rev, dir);
+    let copy = |path: &Path| -> CliResult<PathBuf> {
+        let content = read_file_at_revision(path, rev)?;
+        let copy = dir.join(path.strip_prefix(&base).unwrap_or(path));
+        fs::create_dir_all(copy.parent().unwrap_or(&dir))?;
+        fs::write(&copy, content)?;
+        Ok(copy)
+    };
+
+    let inventory = (|| {
+        // The workspace manifest may not exist at that revision yet
+        if let Some(root_path) = &root_path {
+            if let Err(e) = copy(root_path) {
+                debug!("No workspace manifest at revision {}: {}", rev, e);
+            }
+        }
+        copy(&runtime_lib_path(&manifest_path))?;
+        runtime_inventory(&copy(&manifest_path)?)
+    })();
+    let _ = fs::remove_dir_all(&dir);
+
+    inventory.map_err(|e| e.context(format!("Could not read the runtime at revision {}.", rev)))
+}
+
+/// List the pallets added & removed between the `old` and `new` inventories, and the changes of
+/// the pallets in both.
+fn inventory_changes(old: &Inventory, new: &Inventory) -> Vec<PalletChange> {
+    let mut changes = Vec::new();
+
+    for (key, pallet) in &old.0 {
+        if !new.0.iter().any(|(k, _)| k == key) {
+            changes.push(PalletChange::Removed(key.clone(), pallet.clone()));
+        }
+    }
+
+    for (key, pallet) in &new.0 {
+        let old = match old.0.iter().find(|(k, _)| k == key) {
+            Some((_, old)) => old,
+            None => {
+                changes.push(PalletChange::Added(key.clone(), pallet.clone()));
+                continue;
+            }
+        };
+
+        let mut changed = |field: &str, from: String, to: String| {
+            if from != to {
+                changes.push(PalletChange::Changed {
+                    key: key.clone(),
+                    field: field.to_owned(),
+                    from,
+                    to,
+                });
+            }
+        };
+        let optional = |value: &Option<String>| value.clone().unwrap_or_else(|| "none".to_owned());
+        let list = |values: &[String]| format!("[{}]", values.join(", "));
+
+        changed("package", optional(&old.package), optional(&pallet.package));
+        changed("version", optional(&old.version), optional(&pallet.version));
+        changed("source", optional(&old.source), optional(&pallet.source));
+        changed(
+            "instance",
+            optional(&old.instance.map(|i| i.to_string())),
+            optional(&pallet.instance.map(|i| i.to_string())),
+        );
+        changed("index", old.index.to_string(), pallet.index.to_string());
+        changed("features", list(&old.features), list(&pallet.features));
+        changed("parts", list(&old.parts), list(&pallet.parts));
+        let types = old.types.keys().chain(pallet.types.keys());
+        for ty in types.collect::<std::collections::BTreeSet<_>>() {
+            changed(
+                &format!("type {}", ty),
+                optional(&old.types.get(ty).cloned()),
+                optional(&pallet.types.get(ty).cloned()),
+            );
+        }
+    }
+
+    changes
+}
+
+/// Describe the crate, version, instance & index of `pallet`.
+fn describe(pallet: &ExportedPallet) -> String {
+    let mut description = String::new();
+    if let Some(package) = &pallet.package {
+        description.push_str(&format!("{} ", package));
+    }
+    if let Some(version) = &pallet.version {
+        description.push_str(&format!("v{} ", version));
+    }
+    if let Some(instance) = pallet.instance {
+        description.push_str(&format!("instance {} ", instance));
+    }
+    description.push_str(&format!("at index {}", pallet.index));
+    description
+}
@@ -1,6 +1,9 @@
+use serde_json::{json, Map, Value};
 use std::{
+    error::Error,
     fmt::{Display, Formatter, Result as FmtResult},
     io,
+    path::PathBuf,
 };
 
 pub type CliResult<T> = Result<T, CliError>;
@@ -9,12 +12,49 @@ pub type CliResult<T> = Result<T, CliError>;
 pub enum CliError {
     Dependency(String),
     Generic(String),
+    Git(String),
     Graph(String),
     Io(io::Error),
     Manifest(String),
     Metadata(String),
     Registry(String),
+    Runtime(String),
     Toml(String),
+    /// The pallet crate `name` isn't published in the registry `registry`.
+    PalletNotFound {
+        name: String,
+        registry: String,
+    },
+    /// The `version` of the pallet crate `name` isn't published in the registry `registry`.
+    PalletVersionNotFound {
+        name: String,
+        version: String,
+        registry: String,
+    },
+    /// The runtime library `file` declares its pallets neither with `construct_runtime!` nor
+    /// with a runtime module.
+    ConstructRuntimeNotFound {
+        file: PathBuf,
+    },
+    /// The feature `feature` of the manifest `file` isn't an array of features.
+    FeatureNotArray {
+        feature: String,
+        file: PathBuf,
+    },
+    /// Error `source`, which happened while doing what `message` describes.
+    Context {
+        message: String,
+        source: Box<CliError>,
+    },
+}
+
+/// Format of the messages printed by the CLI.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MessageFormat {
+    /// Messages for humans, on stderr.
+    Human,
+    /// A JSON object per line on stdout, for tools.
+    Json,
 }
 
 impl Display for CliError {
@@ -22,22 +62,217 @@ impl Display for CliError {
         match *self {
             Self::Dependency(ref e) => write!(f, "{}", e),
             Self::Generic(ref e) => write!(f, "{}", e),
+            Self::Git(ref e) => write!(f, "{}", e),
             Self::Graph(ref e) => write!(f, "{}", e),
             Self::Io(ref e) => write!(f, "{}", e),
             Self::Manifest(ref e) => write!(f, "{}", e),
             Self::Metadata(ref e) => write!(f, "{}", e),
             Self::Registry(ref e) => write!(f, "{}", e),
+            Self::Runtime(ref e) => write!(f, "{}", e),
             Self::Toml(ref e) => write!(f, "Could not parse toml file: {}", e),
+            Self::PalletNotFound {
+                ref name,
+                ref registry,
+            } => write!(f, "Pallet {} not found in registry {}.", name, registry),
+            Self::PalletVersionNotFound {
+                ref name,
+                ref version,
+                ref registry,
+            } => write!(
+                f,
+                "Version {} of pallet {} not found in registry {}.",
+                version, name, registry
+            ),
+            Self::ConstructRuntimeNotFound { ref file } => write!(
+                f,
+                "Could not find the runtime pallets declaration in {}.",
+                file.display()
+            ),
+            Self::FeatureNotArray {
+                ref feature,
+                ref file,
+            } => write!(
+                f,
+                "The {} feature in the [features] table of {} is not an array.",
+                feature,
+                file.display()
+            ),
+            Self::Context { ref message, .. } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Error for CliError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            Self::Context { ref source, .. } => Some(&**source),
+            _ => None,
         }
     }
 }
 
 impl CliError {
-    #[allow(dead_code)]
+    /// Describe what was being done when this error happened, keeping it as the source.
+    pub fn context(self, message: impl Into<String>) -> Self {
+        Self::Context {
+            message: message.into(),
+            source: Box::new(self),
+        }
+    }
+
+    /// The error at the end of the chain of sources of this error.
+    pub fn root_cause(&self) -> &Self {
+        match *self {
+            Self::Context { ref source, .. } => source.root_cause(),
+            _ => self,
+        }
+    }
+
+    /// Name of the kind of this error, or of its root cause, e.g. `pallet_not_found`.
+    pub fn kind(&self) -> &'static str {
+        match *self.root_cause() {
+            Self::Dependency(_) => "dependency",
+            Self::Generic(_) => "generic",
+            Self::Git(_) => "git",
+            Self::Graph(_) => "graph",
+            Self::Io(_) => "io",
+            Self::Manifest(_) => "manifest",
+            Self::Metadata(_) => "metadata",
+            Self::Registry(_) => "registry",
+            Self::Runtime(_) => "runtime",
+            Self::Toml(_) => "toml",
+            Self::PalletNotFound { .. } => "pallet_not_found",
+            Self::PalletVersionNotFound { .. } => "pallet_version_not_found",
+            Self::ConstructRuntimeNotFound { .. } => "construct_runtime_not_found",
+            Self::FeatureNotArray { .. } => "feature_not_array",
+            Self::Context { .. } => unreachable!("the root cause has no source"),
+        }
+    }
+
+    /// Exit code of the CLI for this error, per category of its root cause:
+    /// 1 for generic errors, 2 for manifests, 3 for the runtime code, 4 for dependencies,
+    /// 5 for registries, 6 for git & 7 for I/O errors.
+    pub fn exit_code(&self) -> i32 {
+        match *self.root_cause() {
+            Self::Generic(_) | Self::Graph(_) => 1,
+            Self::Manifest(_) | Self::Toml(_) | Self::FeatureNotArray { .. } => 2,
+            Self::Runtime(_) | Self::ConstructRuntimeNotFound { .. } => 3,
+            Self::Dependency(_)
+            | Self::Metadata(_)
+            | Self::PalletNotFound { .. }
+            | Self::PalletVersionNotFound { .. } => 4,
+            Self::Registry(_) => 5,
+            Self::Git(_) => 6,
+            Self::Io(_) => 7,
+            Self::Context { .. } => unreachable!("the root cause has no source"),
+        }
+    }
+
+    /// Suggestion to fix this error, if any.
+    pub fn hint(&self) -> Option<String> {
+        match *self.root_cause() {
+            Self::PalletNotFound { ref registry, .. } => Some(format!(
+                "Check the name of the pallet crate, or use --registry if it isn't published in {}.",
+                registry
+            )),
+            Self::PalletVersionNotFound { ref name, .. } => Some(format!(
+                "Omit the version to add the latest version of {}.",
+                name
+            )),
+            Self::ConstructRuntimeNotFound { .. } => Some(
+                "Declare the runtime pallets with construct_runtime!, or use --runtime to select another runtime crate."
+                    .to_owned(),
+            ),
+            Self::FeatureNotArray { ref feature, .. } => Some(format!(
+                "Declare it as an array of features e.g. {} = [].",
+                feature
+            )),
+            Self::Registry(_) => Some(
+                "Check your network connection, and the registry configuration in .cargo/config."
+                    .to_owned(),
+            ),
+            _ => None,
+        }
+    }
+
+    /// Describe this error as a JSON object, with its chain of sources, hint & the fields of its
+    /// root cause.
+    pub fn to_json(&self) -> Value {
+        let mut causes = Vec::new();
+        let mut source = self.source();
+        while let Some(error) = source {
+            causes.push(error.to_string());
+            source = error.source();
+        }
+
+        let mut details = Map::new();
+        match *self.root_cause() {
+            Self::PalletNotFound {
+                ref name,
+                ref registry,
+            } => {
+                details.insert("name".to_owned(), json!(name));
+                details.insert("registry".to_owned(), json!(registry));
+            }
+            Self::PalletVersionNotFound {
+                ref name,
+                ref version,
+                ref registry,
+            } => {
+                details.insert("name".to_owned(), json!(name));
+                details.insert("version".to_owned(), json!(version));
+                details.insert("registry".to_owned(), json!(registry));
+            }
+            Self::ConstructRuntimeNotFound { ref file } => {
+                details.insert("file".to_owned(), json!(file));
+            }
+            Self::FeatureNotArray {
+                ref feature,
+                ref file,
+            } => {
+                details.insert("feature".to_owned(), json!(feature));
+                details.insert("file".to_owned(), json!(file));
+            }
+            _ => {}
+        }
+
+        json!({
+            "reason": "error",
+            "kind": self.kind(),
+            "code": self.exit_code(),
+            "message": self.to_string(),
+            "causes": causes,
+            "hint": self.hint(),
+            "details": details,
+        })
+    }
+
     /// Print this error and immediately exit the program.
     pub fn exit(&self) -> ! {
-        eprintln!("error: {}", self);
-        ::std::process::exit(1)
+        self.exit_with(MessageFormat::Human)
+    }
+
+    /// Print this error in the message `format` and immediately exit the program, with the exit
+    /// code of its category.
+    pub fn exit_with(&self, format: MessageFormat) -> ! {
+        match format {
+            MessageFormat::Human => {
+                eprintln!("error: {}", self);
+                let mut source = self.source();
+                if source.is_some() {
+                    eprintln!("\nCaused by:");
+                }
+                while let Some(error) = source {
+                    eprintln!("  {}", error);
+                    source = error.source();
+                }
+                if let Some(hint) = self.hint() {
+                    eprintln!("\nhint: {}", hint);
+                }
+            }
+            MessageFormat::Json => println!("{}", self.to_json()),
+        }
+        ::std::process::exit(self.exit_code())
     }
 }
 
@@ -47,6 +282,12 @@ impl From<cargo_deps::Error> for CliError {
     }
 }
 
+impl From<git2::Error> for CliError {
+    fn from(err: git2::Error) -> Self {
+        Self::Git(format!("Git error: {}", err.message()))
+    }
+}
+
 impl From<io::Error> for CliError {
     fn from(err: io::Error) -> Self {
         Self::Io(err)
@@ -70,3 +311,9 @@ impl From<toml::de::Error> for CliError {
         Self::Toml(format!("Could not parse input as TOML: {}", err))
     }
 }
+
+impl From<toml_edit::TomlError> for CliError {
+    fn from(err: toml_edit::TomlError) -> Self {
+        Self::Toml(format!("Could not parse input as TOML: {}", err))
+    }
+}
@@ -0,0 +1,171 @@
+use crate::error::*;
+use crate::manifest::{
+    dependency_features, find_crate_package, find_dependency_key, find_dependency_source,
+    find_dependency_version,
+};
+use crate::output::message;
+use crate::runtime::{pallet_names, pallet_types, read_runtime_lib, runtime_pallets};
+
+use cargo_edit::Dependency;
+use log::debug;
+use serde::{ser::SerializeMap, Serialize, Serializer};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+/// Format of the exported runtime description.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    Toml,
+    Json,
+}
+
+/// A pallet of the runtime, described like in a runtime declaration, with its source & parts.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ExportedPallet {
+    /// Name of the pallet crate, when the key is an alias.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub package: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Source of the pallet crate e.g. `crates-io` or `git+<url>`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance: Option<u32>,
+    pub index: u32,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub features: Vec<String>,
+    /// Parts of the pallet listed in `construct_runtime!`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub parts: Vec<String>,
+    /// Associated types bound in the pallet trait implementation.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub types: BTreeMap<String, String>,
+}
+
+/// The pallets of a runtime by key, in index order.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Inventory(pub Vec<(String, ExportedPallet)>);
+
+impl Serialize for Inventory {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (key, pallet) in &self.0 {
+            map.serialize_entry(key, pallet)?;
+        }
+        map.end()
+    }
+}
+
+#[derive(Serialize)]
+struct Document<'a> {
+    pallets: &'a Inventory,
+}
+
+/// Write the declaration of the pallets of the runtime whose manifest is `manifest_path` in
+/// `format`, to `output` or stdout.
+pub fn execute_export(
+    manifest_path: &Path,
+    format: ExportFormat,
+    output: Option<&Path>,
+) -> CliResult<()> {
+    debug!("Manifest path: {:?}", manifest_path);
+    debug!("Format: {:?}", format);
+    debug!("Output: {:?}", output);
+
+    let inventory = runtime_inventory(manifest_path)?;
+    let document = Document {
+        pallets: &inventory,
+    };
+    let exported = match format {
+        ExportFormat::Toml => toml::to_string(&document)
+            .map_err(|e| CliError::Toml(format!("Error exporting runtime: {}", e)))?,
+        ExportFormat::Json => serde_json::to_string_pretty(&document)
+            .map(|json| json + "\n")
+            .map_err(|e| CliError::Generic(format!("Error exporting runtime: {}", e)))?,
+    };
+
+    match output {
+        Some(output) => {
+            fs::write(output, exported)?;
+            message(format!(
+                "Exported {} pallets of your node runtime to {}.",
+                inventory.0.len(),
+                output.display()
+            ));
+        }
+        None => io::stdout().write_all(exported.as_bytes())?,
+    }
+
+    Ok(())
+}
+
+/// List the pallets of the runtime whose manifest is `manifest_path`, keyed like in a runtime
+/// declaration: by pallet crate name, or by alias when the pallet name in the runtime isn't
+/// derived from its crate name.
+pub fn runtime_inventory(manifest_path: &Path) -> CliResult<Inventory> {
+    let runtime = read_runtime_lib(manifest_path)?;
+    let mut pallets = runtime_pallets(&runtime)?;
+    pallets.sort_by_key(|pallet| pallet.index);
+
+    let mut inventory = Inventory::default();
+    for pallet in pallets {
+        let package = find_crate_package(manifest_path, &pallet.path)?;
+        let instance = pallet.instance.as_deref().and_then(|instance| {
+            instance
+                .rsplit("::")
+                .next()
+                .and_then(|instance| instance.strip_prefix("Instance"))
+                .and_then(|instance| instance.parse().ok())
+        });
+
+        // Key the pallet by its crate name if the pallet name derives from it
+        let mut key = None;
+        if let Some(package) = &package {
+            let dependency = Dependency::new(package);
+            let (_, name) = pallet_names(manifest_path, &dependency, &None, instance)?;
+            if name == pallet.name && !inventory.0.iter().any(|(k, _)| k == package) {
+                key = Some(package.clone());
+            }
+        }
+        let (key, alias_package) = match key {
+            Some(key) => (key, None),
+            None => (
+                inflector::cases::snakecase::to_snake_case(&pallet.name),
+                package.clone(),
+            ),
+        };
+
+        let dependency_key = match &package {
+            Some(package) => find_dependency_key(manifest_path, package)?,
+            None => None,
+        };
+        let (version, source, features) = match &dependency_key {
+            Some(dependency_key) => (
+                find_dependency_version(manifest_path, dependency_key)?,
+                find_dependency_source(manifest_path, dependency_key)?,
+                dependency_features(manifest_path, dependency_key)?.enabled,
+            ),
+            None => (None, None, Vec::new()),
+        };
+
+        let exported = ExportedPallet {
+            package: alias_package,
+            version,
+            source,
+            instance,
+            index: pallet.index,
+            features,
+            parts: pallet.parts.clone(),
+            types: pallet_types(&runtime, &pallet)?,
+        };
+        inventory.0.push((key, exported));
+    }
+
+    Ok(inventory)
+}
@@ -0,0 +1,158 @@
+use crate::error::*;
+use crate::manifest::{
+    dependency_features, find_dependency_key, find_dependency_version, set_dependency_features,
+};
+use crate::output::message;
+use crate::registry::crate_features;
+
+use cargo_edit::{registry_url, Dependency};
+use log::{debug, warn};
+use serde_json::json;
+use std::path::Path;
+
+/// List the features of a pallet of the runtime whose manifest is `manifest_path`, or enable &
+/// disable some of them. With the JSON `format`, the features of the pallet are written to stdout
+/// as a JSON object, after the changes if any.
+pub fn execute_features(
+    manifest_path: &Path,
+    pallet: &str,
+    enable: &[String],
+    disable: &[String],
+    via: Option<&str>,
+    registry: Option<&str>,
+    format: MessageFormat,
+) -> CliResult<()> {
+    debug!("Manifest path: {:?}", manifest_path);
+    debug!("Pallet: {}", pallet);
+    debug!("Enable: {:?}", enable);
+    debug!("Disable: {:?}", disable);
+    debug!("Via: {:?}", via);
+    debug!("Registry: {:?}", registry);
+
+    let key = find_dependency_key(manifest_path, pallet)?.ok_or_else(|| {
+        CliError::Dependency(format!(
+            "Pallet {} is not a dependency of your node runtime.",
+            pallet
+        ))
+    })?;
+    debug!("Dependency key: {}", key);
+
+    if enable.is_empty() && disable.is_empty() {
+        return print_features(manifest_path, pallet, &key, format);
+    }
+
+    // Lookup registry URL
+    let reg_url =
+        registry_url(manifest_path, registry).map_err(|e| CliError::Registry(e.to_string()))?;
+    debug!("Registry URL: {}", reg_url);
+
+    let dependency = match find_dependency_version(manifest_path, &key)? {
+        Some(version) => Dependency::new(pallet).set_version(&version),
+        None => Dependency::new(pallet),
+    };
+    validate_features(&dependency, enable, crate_features(&reg_url, &dependency)?)?;
+
+    set_dependency_features(manifest_path, &key, enable, disable, via)?;
+
+    if !enable.is_empty() {
+        message(format!(
+            "Enabled pallet {} features {}{} in your node runtime manifest.",
+            pallet,
+            enable.join(", "),
+            via.map(|via| format!(" via {}", via)).unwrap_or_default()
+        ));
+    }
+    if !disable.is_empty() {
+        message(format!(
+            "Disabled pallet {} features {} in your node runtime manifest.",
+            pallet,
+            disable.join(", ")
+        ));
+    }
+
+    match format {
+        MessageFormat::Json => print_features(manifest_path, pallet, &key, format),
+        MessageFormat::Human => Ok(()),
+    }
+}
+
+/// Write the features of the runtime dependency `key` on the `pallet` crate to stdout, a feature
+/// per line or as a JSON object.
+fn print_features(
+    manifest_path: &Path,
+    pallet: &str,
+    key: &str,
+    format: MessageFormat,
+) -> CliResult<()> {
+    let features = dependency_features(manifest_path, key)?;
+    match format {
+        MessageFormat::Human => {
+            for feature in features.enabled {
+                println!("{}", feature);
+            }
+            for (runtime_feature, feature) in features.forwarded {
+                println!("{} (via {})", feature, runtime_feature);
+            }
+        }
+        MessageFormat::Json => {
+            let forwarded = features
+                .forwarded
+                .iter()
+                .map(|(runtime_feature, feature)| json!({ "feature": feature, "via": runtime_feature }))
+                .collect::<Vec<_>>();
+            println!(
+                "{}",
+                json!({
+                    "reason": "pallet-features",
+                    "pallet": pallet,
+                    "key": key,
+                    "enabled": features.enabled,
+                    "forwarded": forwarded,
+                })
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Check that the version of `dependency` exposes the `features`, among its `available` features
+/// e.g. according to the registry index. Features can't be checked if the version isn't found in
+/// the local copy of the index.
+pub fn validate_features(
+    dependency: &Dependency,
+    features: &[String],
+    available: Option<Vec<String>>,
+) -> CliResult<()> {
+    if features.is_empty() {
+        return Ok(());
+    }
+
+    let available = match available {
+        Some(available) => available,
+        None => {
+            warn!(
+                "Couldn't find pallet {} v{} in the registry index, skipping features validation.",
+                dependency.name,
+                dependency.version().unwrap_or_default()
+            );
+            return Ok(());
+        }
+    };
+
+    let unknown = features
+        .iter()
+        .filter(|feature| !available.contains(feature))
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    if !unknown.is_empty() {
+        return Err(CliError::Dependency(format!(
+            "Pallet {} v{} has no feature {}, available features: {}",
+            dependency.name,
+            dependency.version().unwrap_or_default(),
+            unknown.join(", "),
+            available.join(", ")
+        )));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,232 @@
+use crate::error::CliResult;
+use crate::metadata::PalletMetadata;
+use crate::runtime::{last_line_indent, matching_brace, trailing_blank_lines, RuntimePallet};
+use crate::workspace::find_node_file;
+
+use std::{fs, path::Path};
+
+use inflector::cases::snakecase::to_snake_case;
+use log::{debug, warn};
+use regex::Regex;
+
+lazy_static! {
+    static ref TESTNET_GENESIS_REGEX: Regex = Regex::new(r"fn\s+testnet_genesis\s*\(").unwrap();
+    static ref GENESIS_CONFIG_REGEX: Regex =
+        Regex::new(r"\b(?:Runtime)?GenesisConfig\s*\{").unwrap();
+    static ref GENESIS_FIELD_REGEX: Regex =
+        Regex::new(r"(?P<field>\w+)\s*:\s*(?P<option>Some\(\s*)?(?P<name>\w+)Config\s*[{(:]")
+            .unwrap();
+    static ref GENESIS_IMPORT_REGEX: Regex =
+        Regex::new(r"use\s+[\w:]+::\{(?P<items>[^}]*\b(?:Runtime)?GenesisConfig\b[^}]*)\}")
+            .unwrap();
+}
+
+/// How the pallets genesis config fields are declared in the `GenesisConfig` of the chain spec.
+#[derive(Clone, Copy, Debug)]
+struct GenesisStyle {
+    /// Whether fields are named after the pallet module e.g. `pallet_balances`, rather than after
+    /// the pallet name in the runtime e.g. `balances`.
+    module_names: bool,
+    /// Whether fields are optional e.g. `Some(BalancesConfig { .. })`.
+    option: bool,
+}
+
+impl GenesisStyle {
+    fn detect(fields: &str) -> Self {
+        let mut style = Self {
+            module_names: false,
+            option: true,
+        };
+        let mut fields = GENESIS_FIELD_REGEX.captures_iter(fields).peekable();
+        if let Some(caps) = fields.peek() {
+            style.option = caps.name("option").is_some();
+        }
+        style.module_names = fields.any(|caps| caps["field"] != to_snake_case(&caps["name"]));
+        style
+    }
+
+    fn field_name(&self, pallet: &RuntimePallet) -> String {
+        match (self.module_names, &pallet.instance) {
+            (true, Some(instance)) => format!("{}_{}", pallet.path, instance),
+            (true, None) => pallet.path.clone(),
+            (false, _) => to_snake_case(&pallet.name),
+        }
+    }
+}
+
+/// Add the genesis config of `pallet` to the `testnet_genesis` of the node chain spec, if the
+/// pallet has one. Returns whether the chain spec was updated.
+pub fn add_pallet_to_chain_spec(
+    manifest_path: &Path,
+    pallet: &RuntimePallet,
+    metadata: &PalletMetadata,
+) -> CliResult<bool> {
+    let genesis_config = match metadata.genesis_config() {
+        Some(genesis_config) => genesis_config,
+        None => return Ok(false),
+    };
+    let chain_spec_path = match find_node_file(manifest_path, "chain_spec.rs")? {
+        Some(chain_spec_path) => chain_spec_path,
+        None => {
+            debug!("Couldn't find the node chain spec.");
+            return Ok(false);
+        }
+    };
+    debug!("Chain spec: {:?}", chain_spec_path);
+
+    let mut buffer = fs::read_to_string(&chain_spec_path)?;
+    let (open, close) = match genesis_config_literal(&buffer) {
+        Some(literal) => literal,
+        None => {
+            warn!(
+                "Couldn't find the GenesisConfig of testnet_genesis in {:?}, skipping {} genesis config.",
+                chain_spec_path, pallet.name
+            );
+            return Ok(false);
+        }
+    };
+
+    let style = GenesisStyle::detect(&buffer[open + 1..close]);
+    let field = style.field_name(pallet);
+    if Regex::new(&format!(r"\b{}\s*:", field))?.is_match(&buffer[open + 1..close]) {
+        debug!("Genesis config {} already exists.", field);
+        return Ok(false);
+    }
+
+    // Insert the pallet genesis config after the last one, with the same indentation
+    let config = format!("{}Config", pallet.name);
+    let last = buffer[..close].trim_end().len();
+    let indent = if last > open + 1 {
+        last_line_indent(&buffer[..last])
+    } else {
+        format!("{}\t", last_line_indent(&buffer[..open]))
+    };
+    let unit = if indent.starts_with('\t') {
+        "\t"
+    } else {
+        "    "
+    };
+
+    let mut value = if genesis_config.is_empty() {
+        format!("{}::default()", config)
+    } else {
+        let mut value = format!("{} {{\n", config);
+        for (name, default) in genesis_config {
+            value.push_str(&format!("{}{}{}: {},\n", indent, unit, name, default));
+        }
+        value.push_str(&format!("{}}}", indent));
+        value
+    };
+    if style.option {
+        value = format!("Some({})", value);
+    }
+
+    let mut entry = String::new();
+    if last > open + 1 && !buffer[..last].ends_with(',') {
+        entry.push(',');
+    }
+    entry.push_str(&format!("\n{}{}: {},", indent, field, value));
+    buffer.insert_str(last, &entry);
+
+    // Import the pallet genesis config type from the runtime
+    if let Some(items) = GENESIS_IMPORT_REGEX
+        .captures(&buffer)
+        .and_then(|caps| caps.name("items"))
+    {
+        if !Regex::new(&format!(r"\b{}\b", config))?.is_match(items.as_str()) {
+            let end = items.start() + items.as_str().trim_end().len();
+            let separator = if buffer[..end].ends_with(',') {
+                " "
+            } else {
+                ", "
+            };
+            buffer.insert_str(end, &format!("{}{}", separator, config));
+        }
+    }
+
+    fs::write(&chain_spec_path, buffer)?;
+
+    Ok(true)
+}
+
+/// Remove the genesis config of `pallet` from the `testnet_genesis` of the node chain spec.
+/// Returns whether the chain spec was updated.
+pub fn remove_pallet_from_chain_spec(
+    manifest_path: &Path,
+    pallet: &RuntimePallet,
+) -> CliResult<bool> {
+    let chain_spec_path = match find_node_file(manifest_path, "chain_spec.rs")? {
+        Some(chain_spec_path) => chain_spec_path,
+        None => return Ok(false),
+    };
+
+    let mut buffer = fs::read_to_string(&chain_spec_path)?;
+    let (open, close) = match genesis_config_literal(&buffer) {
+        Some(literal) => literal,
+        None => return Ok(false),
+    };
+
+    let fields = [
+        GenesisStyle {
+            module_names: true,
+            option: true,
+        }
+        .field_name(pallet),
+        to_snake_case(&pallet.name),
+    ];
+    let field = Regex::new(&format!(r"(?m)^[ \t]*(?:{})\s*:", fields.join("|")))?;
+    let start = match field.find(&buffer[open + 1..close]) {
+        Some(mat) => open + 1 + mat.start(),
+        None => return Ok(false),
+    };
+    let end = field_end(&buffer, start, close);
+    let end = end + trailing_blank_lines(&buffer[end..]).min(1);
+    buffer.replace_range(start..end, "");
+
+    // Remove the pallet genesis config type import
+    let import = Regex::new(&format!(r",\s*\b{}Config\b", pallet.name))?;
+    let first_import = Regex::new(&format!(r"\b{}Config\b\s*,?\s*", pallet.name))?;
+    if let Some(items) = GENESIS_IMPORT_REGEX
+        .captures(&buffer)
+        .and_then(|caps| caps.name("items"))
+    {
+        let range = items.start()..items.end();
+        let items = if import.is_match(items.as_str()) {
+            import.replace(items.as_str(), "")
+        } else {
+            first_import.replace(items.as_str(), "")
+        }
+        .into_owned();
+        buffer.replace_range(range, &items);
+    }
+
+    fs::write(&chain_spec_path, buffer)?;
+
+    Ok(true)
+}
+
+/// Find the braces of the `GenesisConfig` struct expression returned by `testnet_genesis`.
+fn genesis_config_literal(chain_spec: &str) -> Option<(usize, usize)> {
+    let function = TESTNET_GENESIS_REGEX.find(chain_spec)?;
+    let body = function.end() + chain_spec[function.end()..].find('{')?;
+    let body_end = matching_brace(chain_spec, body)?;
+
+    let literal = GENESIS_CONFIG_REGEX.find(&chain_spec[body + 1..body_end])?;
+    let open = body + 1 + literal.end() - 1;
+    Some((open, matching_brace(chain_spec, open)?))
+}
+
+/// Find the end of the struct field starting at `start`, after its trailing comma if any.
+fn field_end(s: &str, start: usize, close: usize) -> usize {
+    let mut depth = 0;
+    for (i, c) in s[start..close].char_indices() {
+        match c {
+            '{' | '(' | '[' => depth += 1,
+            '}' | ')' | ']' => depth -= 1,
+            ',' if depth == 0 => return start + i + 1,
+            _ => {}
+        }
+    }
+    // Last field without trailing comma
+    start + s[start..close].trim_end().len()
+}
@@ -0,0 +1,29 @@
+use crate::error::{CliError, CliResult};
+
+use std::path::Path;
+
+use git2::Repository;
+
+/// Read the content of `path` at the git revision `rev` of the repository containing it.
+pub fn read_file_at_revision(path: &Path, rev: &str) -> CliResult<String> {
+    let path = path.canonicalize()?;
+    let repo = Repository::discover(&path)?;
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| CliError::Git("Cannot read files from a bare repository".to_owned()))?
+        .canonicalize()?;
+    let relative_path = path.strip_prefix(&workdir).map_err(|_| {
+        CliError::Git(format!(
+            "{:?} is not in the repository at {:?}",
+            path, workdir
+        ))
+    })?;
+
+    let spec = format!(
+        "{}:{}",
+        rev,
+        relative_path.to_string_lossy().replace('\\', "/")
+    );
+    let blob = repo.revparse_single(&spec)?.peel_to_blob()?;
+    Ok(String::from_utf8(blob.content().to_vec())?)
+}
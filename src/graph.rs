@@ -2,14 +2,16 @@ use crate::error::*;
 use crate::metadata::Manifest;
 
 use cargo_deps::{get_dep_graph, render_dep_graph, Config};
-use clap::ArgMatches;
+use serde_json::json;
 use std::{
     fs,
     io::{self, Write},
+    path::Path,
 };
 
 lazy_static! {
-    static ref FRAME: [String; 35] = [
+    /// The FRAME pallets, shown in the dependency graph & for which metadata templates are built in.
+    pub static ref FRAME: [String; 35] = [
         "pallet-assets".to_owned(),
         "pallet-aura".to_owned(),
         "pallet-authority-discovery".to_owned(),
@@ -48,24 +50,98 @@ lazy_static! {
     ];
 }
 
-pub fn execute_graph(m: &ArgMatches) -> CliResult<()> {
+/// Format of the runtime dependency graph.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum GraphFormat {
+    /// Graphviz DOT format.
+    #[default]
+    Dot,
+    /// A JSON object with the `nodes` & `edges` of the graph.
+    Json,
+}
+
+/// Options of the runtime dependency graph.
+#[derive(Clone, Debug, Default)]
+pub struct GraphOptions {
+    /// Include the dependency versions on the graph nodes.
+    pub include_versions: bool,
+    /// Format the graph is written in.
+    pub format: GraphFormat,
+}
+
+/// Write the graph of the FRAME pallets the runtime whose manifest is `manifest_path` depends on
+/// to stdout, in the DOT or JSON format.
+pub fn execute_graph(manifest_path: &Path, options: &GraphOptions) -> CliResult<()> {
     // debug!("Manifest path: {:?}", manifest_path);
 
     let mut cfg = Config::default();
-    cfg.manifest_path = m.value_of("manifest-path").unwrap_or("Cargo.toml").into();
-    cfg.include_versions = m.is_present("include-versions");
+    cfg.manifest_path = manifest_path.to_string_lossy().into_owned();
+    cfg.include_versions = options.include_versions;
     let manifest = read_manifest(&cfg.manifest_path)?;
 
-    let mut filter = vec![manifest.package().as_ref().unwrap().name().to_owned()];
+    let package = manifest.package().as_ref().ok_or_else(|| {
+        CliError::Manifest(format!(
+            "{} is not a crate manifest, the runtime crate can't be found.",
+            manifest_path.display()
+        ))
+    })?;
+    let mut filter = vec![package.name().to_owned()];
     filter.append(&mut FRAME.to_vec());
     cfg.filter = Some(filter);
     cfg.transitive_deps = false;
 
     // Get dependency graph & render it
-    let o = get_dep_graph(cfg).and_then(render_dep_graph)?;
-    io::stdout()
-        .write_all(&o.into_bytes())
-        .expect("Unable to write graph");
+    let o = match options.format {
+        GraphFormat::Dot => get_dep_graph(cfg).and_then(render_dep_graph)?,
+        GraphFormat::Json => {
+            let graph = get_dep_graph(cfg)?;
+            // Like the DOT rendering, leave out the orphan nodes, of unknown kind, & their edges
+            // cargo-deps doesn't export `DepKind`, so test the flags its `kind()` is built on
+            let kinds = graph
+                .nodes
+                .iter()
+                .map(|node| {
+                    if node.is_regular {
+                        Some("regular")
+                    } else if node.is_build {
+                        Some("build")
+                    } else if node.is_dev {
+                        Some("dev")
+                    } else if node.is_optional {
+                        Some("optional")
+                    } else {
+                        None
+                    }
+                })
+                .collect::<Vec<_>>();
+            let rendered = |i: usize| kinds[i].is_some() || graph.cfg.include_orphans;
+            let nodes = graph
+                .nodes
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| rendered(*i))
+                .map(|(i, node)| {
+                    json!({
+                        "id": i,
+                        "name": node.name,
+                        "version": node.ver,
+                        "kind": kinds[i].unwrap_or("unknown"),
+                    })
+                })
+                .collect::<Vec<_>>();
+            let edges = graph
+                .edges
+                .iter()
+                .filter(|edge| rendered(edge.0) && rendered(edge.1))
+                .map(|edge| json!({ "from": edge.0, "to": edge.1 }))
+                .collect::<Vec<_>>();
+            format!(
+                "{}\n",
+                json!({ "reason": "graph", "nodes": nodes, "edges": edges })
+            )
+        }
+    };
+    io::stdout().write_all(&o.into_bytes())?;
 
     Ok(())
 }
@@ -0,0 +1,436 @@
+use crate::add::add_pallets;
+use crate::error::*;
+use crate::manifest::{
+    add_pallet_to_manifest, DependencyKind, DependencySection, PalletDependencyOptions,
+};
+use crate::new_pallet::CODEC_VERSION;
+use crate::output::message;
+use crate::spec::PalletSpec;
+use crate::transaction::Snapshot;
+use crate::workspace::{add_workspace_member, find_enclosing_workspace_root};
+
+use cargo_edit::Dependency;
+use log::debug;
+use semver::Version;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Version of FRAME the generated runtime code is written for.
+pub const FRAME_VERSION: &str = "2.0.0";
+
+/// Major version of the FRAME versions the generated runtime code compiles with: later versions
+/// renamed `frame_system::Trait` & dropped `AllModules`.
+const FRAME_MAJOR_VERSION: u64 = 2;
+
+/// The crates the runtime code depends on, versioned like FRAME, but for `parity-scale-codec`.
+const RUNTIME_DEPENDENCIES: [&str; 13] = [
+    "parity-scale-codec",
+    "frame-executive",
+    "frame-support",
+    "frame-system",
+    "sp-api",
+    "sp-block-builder",
+    "sp-core",
+    "sp-inherents",
+    "sp-offchain",
+    "sp-runtime",
+    "sp-std",
+    "sp-transaction-pool",
+    "sp-version",
+];
+
+/// Version of `substrate-wasm-builder-runner`, building the runtime Wasm binary.
+const WASM_BUILDER_RUNNER_VERSION: &str = "1.0.6";
+
+/// Templates of the runtime crate files, referring to the crate name as `{name}` & to the FRAME
+/// version as `{frame_version}`. The dependencies & pallets are added like with `add`.
+const MANIFEST_TEMPLATE: &str = r#"[package]
+name = "{name}"
+version = "0.1.0"
+edition = "2018"
+publish = false
+build = "build.rs"
+
+[package.metadata.docs.rs]
+targets = ["x86_64-unknown-linux-gnu"]
+
+[dependencies]
+
+[build-dependencies]
+
+[features]
+default = ["std"]
+std = [
+]
+"#;
+
+const BUILD_TEMPLATE: &str = r#"use substrate_wasm_builder_runner::WasmBuilder;
+
+fn main() {
+    WasmBuilder::new()
+        .with_current_project()
+        .with_wasm_builder_from_crates("{frame_version}")
+        .export_heap_base()
+        .import_memory()
+        .build()
+}
+"#;
+
+const LIB_TEMPLATE: &str = r#"#![cfg_attr(not(feature = "std"), no_std)]
+// `construct_runtime!` does a lot of recursion and requires us to increase the limit to 256.
+#![recursion_limit = "256"]
+
+// Make the WASM binary available.
+#[cfg(feature = "std")]
+include!(concat!(env!("OUT_DIR"), "/wasm_binary.rs"));
+
+use sp_api::impl_runtime_apis;
+use sp_core::OpaqueMetadata;
+use sp_runtime::{
+    create_runtime_str, generic,
+    traits::{BlakeTwo256, Block as BlockT, IdentifyAccount, IdentityLookup, Verify},
+    transaction_validity::{TransactionSource, TransactionValidity},
+    ApplyExtrinsicResult, MultiSignature,
+};
+use sp_std::prelude::*;
+#[cfg(feature = "std")]
+use sp_version::NativeVersion;
+use sp_version::RuntimeVersion;
+
+pub use frame_support::{
+    construct_runtime, parameter_types,
+    weights::{
+        constants::{BlockExecutionWeight, ExtrinsicBaseWeight, RocksDbWeight, WEIGHT_PER_SECOND},
+        IdentityFee, Weight,
+    },
+    StorageValue,
+};
+pub use sp_runtime::{Perbill, Permill};
+
+/// An index to a block.
+pub type BlockNumber = u32;
+
+/// Alias to 512-bit hash when used in the context of a transaction signature on the chain.
+pub type Signature = MultiSignature;
+
+/// Some way of identifying an account on the chain.
+pub type AccountId = <<Signature as Verify>::Signer as IdentifyAccount>::AccountId;
+
+/// The type for looking up accounts.
+pub type AccountIndex = u32;
+
+/// Balance of an account.
+pub type Balance = u128;
+
+/// Index of a transaction in the chain.
+pub type Index = u32;
+
+/// A hash of some data used by the chain.
+pub type Hash = sp_core::H256;
+
+/// Digest item type.
+pub type DigestItem = generic::DigestItem<Hash>;
+
+/// Opaque types, used by the CLI to instantiate machinery that don't need to know the specifics
+/// of the runtime.
+pub mod opaque {
+    use super::*;
+
+    pub use sp_runtime::OpaqueExtrinsic as UncheckedExtrinsic;
+
+    /// Opaque block header type.
+    pub type Header = generic::Header<BlockNumber, BlakeTwo256>;
+    /// Opaque block type.
+    pub type Block = generic::Block<Header, UncheckedExtrinsic>;
+    /// Opaque block identifier type.
+    pub type BlockId = generic::BlockId<Block>;
+}
+
+pub const VERSION: RuntimeVersion = RuntimeVersion {
+    spec_name: create_runtime_str!("{name}"),
+    impl_name: create_runtime_str!("{name}"),
+    authoring_version: 1,
+    spec_version: 1,
+    impl_version: 1,
+    apis: RUNTIME_API_VERSIONS,
+    transaction_version: 1,
+};
+
+/// The version information used to identify this runtime when compiled natively.
+#[cfg(feature = "std")]
+pub fn native_version() -> NativeVersion {
+    NativeVersion {
+        runtime_version: VERSION,
+        can_author_with: Default::default(),
+    }
+}
+
+parameter_types! {
+    pub const BlockHashCount: BlockNumber = 2400;
+    /// We allow for 2 seconds of compute with a 6 second average block time.
+    pub const MaximumBlockWeight: Weight = 2 * WEIGHT_PER_SECOND;
+    pub const AvailableBlockRatio: Perbill = Perbill::from_percent(75);
+    /// Assume 10% of weight for average on_initialize calls.
+    pub MaximumExtrinsicWeight: Weight = AvailableBlockRatio::get()
+        .saturating_sub(Perbill::from_percent(10)) * MaximumBlockWeight::get();
+    pub const MaximumBlockLength: u32 = 5 * 1024 * 1024;
+    pub const Version: RuntimeVersion = VERSION;
+}
+
+impl frame_system::Trait for Runtime {
+    type BaseCallFilter = ();
+    type AccountId = AccountId;
+    type Call = Call;
+    type Lookup = IdentityLookup<AccountId>;
+    type Index = Index;
+    type BlockNumber = BlockNumber;
+    type Hash = Hash;
+    type Hashing = BlakeTwo256;
+    type Header = generic::Header<BlockNumber, BlakeTwo256>;
+    type Event = Event;
+    type Origin = Origin;
+    type BlockHashCount = BlockHashCount;
+    type MaximumBlockWeight = MaximumBlockWeight;
+    type DbWeight = RocksDbWeight;
+    type BlockExecutionWeight = BlockExecutionWeight;
+    type ExtrinsicBaseWeight = ExtrinsicBaseWeight;
+    type MaximumExtrinsicWeight = MaximumExtrinsicWeight;
+    type MaximumBlockLength = MaximumBlockLength;
+    type AvailableBlockRatio = AvailableBlockRatio;
+    type Version = Version;
+    type PalletInfo = PalletInfo;
+    type AccountData = ();
+    type OnNewAccount = ();
+    type OnKilledAccount = ();
+    type SystemWeightInfo = ();
+}
+
+construct_runtime!(
+    pub enum Runtime where
+        Block = Block,
+        NodeBlock = opaque::Block,
+        UncheckedExtrinsic = UncheckedExtrinsic
+    {
+        System: frame_system::{Module, Call, Config, Storage, Event<T>} = 0,
+    }
+);
+
+/// The address format for describing accounts.
+pub type Address = AccountId;
+/// Block header type as expected by this runtime.
+pub type Header = generic::Header<BlockNumber, BlakeTwo256>;
+/// Block type as expected by this runtime.
+pub type Block = generic::Block<Header, UncheckedExtrinsic>;
+/// A Block signed with a Justification.
+pub type SignedBlock = generic::SignedBlock<Block>;
+/// BlockId type as expected by this runtime.
+pub type BlockId = generic::BlockId<Block>;
+/// The SignedExtension to the basic transaction logic.
+pub type SignedExtra = (
+    frame_system::CheckSpecVersion<Runtime>,
+    frame_system::CheckTxVersion<Runtime>,
+    frame_system::CheckGenesis<Runtime>,
+    frame_system::CheckEra<Runtime>,
+    frame_system::CheckNonce<Runtime>,
+    frame_system::CheckWeight<Runtime>,
+);
+/// Unchecked extrinsic type as expected by this runtime.
+pub type UncheckedExtrinsic = generic::UncheckedExtrinsic<Address, Call, Signature, SignedExtra>;
+/// Extrinsic type that has already been checked.
+pub type CheckedExtrinsic = generic::CheckedExtrinsic<AccountId, Call, SignedExtra>;
+/// Executive: handles dispatch to the various modules.
+pub type Executive = frame_executive::Executive<
+    Block,
+    frame_system::ChainContext<Runtime>,
+    Runtime,
+    AllModules,
+>;
+
+impl_runtime_apis! {
+    impl sp_api::Core<Block> for Runtime {
+        fn version() -> RuntimeVersion {
+            VERSION
+        }
+
+        fn execute_block(block: Block) {
+            Executive::execute_block(block)
+        }
+
+        fn initialize_block(header: &<Block as BlockT>::Header) {
+            Executive::initialize_block(header)
+        }
+    }
+
+    impl sp_api::Metadata<Block> for Runtime {
+        fn metadata() -> OpaqueMetadata {
+            Runtime::metadata().into()
+        }
+    }
+
+    impl sp_block_builder::BlockBuilder<Block> for Runtime {
+        fn apply_extrinsic(extrinsic: <Block as BlockT>::Extrinsic) -> ApplyExtrinsicResult {
+            Executive::apply_extrinsic(extrinsic)
+        }
+
+        fn finalize_block() -> <Block as BlockT>::Header {
+            Executive::finalize_block()
+        }
+
+        fn inherent_extrinsics(data: sp_inherents::InherentData) -> Vec<<Block as BlockT>::Extrinsic> {
+            data.create_extrinsics()
+        }
+
+        fn check_inherents(
+            block: Block,
+            data: sp_inherents::InherentData,
+        ) -> sp_inherents::CheckInherentsResult {
+            data.check_extrinsics(&block)
+        }
+
+        fn random_seed() -> <Block as BlockT>::Hash {
+            System::parent_hash()
+        }
+    }
+
+    impl sp_transaction_pool::runtime_api::TaggedTransactionQueue<Block> for Runtime {
+        fn validate_transaction(
+            source: TransactionSource,
+            tx: <Block as BlockT>::Extrinsic,
+        ) -> TransactionValidity {
+            Executive::validate_transaction(source, tx)
+        }
+    }
+
+    impl sp_offchain::OffchainWorkerApi<Block> for Runtime {
+        fn offchain_worker(header: &<Block as BlockT>::Header) {
+            Executive::offchain_worker(header)
+        }
+    }
+}
+"#;
+
+/// Generate a runtime crate named `name` in `dir` depending on FRAME `frame_version`, with the
+/// `pallets` added to it.
+pub fn execute_init_runtime(
+    dir: &Path,
+    name: &str,
+    pallets: &[PalletSpec],
+    frame_version: &str,
+    registry: Option<&str>,
+) -> CliResult<()> {
+    debug!("Directory: {:?}", dir);
+    debug!("Name: {}", name);
+    debug!("Pallets: {:?}", pallets);
+    debug!("FRAME version: {}", frame_version);
+    debug!("Registry: {:?}", registry);
+
+    let version = Version::parse(frame_version)
+        .map_err(|e| CliError::Generic(format!("{}: {}", frame_version, e)))?;
+    if version.major != FRAME_MAJOR_VERSION {
+        return Err(CliError::Generic(format!(
+            "The runtime template is written for FRAME {}.x, not FRAME {}.",
+            FRAME_MAJOR_VERSION, frame_version
+        )));
+    }
+
+    if dir.exists() && fs::read_dir(dir)?.next().is_some() {
+        return Err(CliError::Generic(format!(
+            "Directory {} already exists and is not empty.",
+            dir.display()
+        )));
+    }
+
+    // Remove the created files if the runtime can't be generated, and restore the workspace it
+    // joins, which doesn't list the runtime as a member yet when the runtime files are read
+    let mut snapshot = Snapshot::default();
+    for file in &["Cargo.toml", "build.rs", "src/lib.rs"] {
+        snapshot.add_missing(&dir.join(file));
+    }
+    let created = (|| {
+        fs::create_dir_all(dir.join("src"))?;
+        let manifest_path = write_runtime_crate(dir, name, frame_version, registry)?;
+        message(format!("Created runtime {} in {}.", name, dir.display()));
+        snapshot.add_runtime(&manifest_path)?;
+
+        if let Some(root_path) = find_enclosing_workspace_root(&manifest_path)? {
+            snapshot.add_file(&root_path)?;
+            add_workspace_member(&root_path, dir)?;
+        }
+
+        // Pallets are added with the FRAME version of the runtime, unless they request one
+        let pallets = pallets
+            .iter()
+            .map(|spec| PalletSpec {
+                version: match (&spec.version, &spec.path) {
+                    (None, None) => Some(frame_version.to_owned()),
+                    _ => spec.version.clone(),
+                },
+                ..spec.clone()
+            })
+            .collect::<Vec<_>>();
+        if !pallets.is_empty() {
+            add_pallets(&manifest_path, &pallets, registry)?;
+        }
+        Ok(())
+    })();
+
+    created.map_err(|e| {
+        snapshot.restore();
+        e
+    })
+}
+
+/// Write the manifest, build script & library of the runtime crate `name` in `dir`, declaring
+/// the System pallet & the runtime APIs every node uses, and add its dependencies to the manifest
+/// like `add` does. Returns the path of the manifest.
+fn write_runtime_crate(
+    dir: &Path,
+    name: &str,
+    frame_version: &str,
+    registry: Option<&str>,
+) -> CliResult<PathBuf> {
+    let render = |template: &str| {
+        template
+            .replace("{name}", name)
+            .replace("{frame_version}", frame_version)
+    };
+
+    let manifest_path = dir.join("Cargo.toml");
+    fs::write(&manifest_path, render(MANIFEST_TEMPLATE))?;
+    fs::write(dir.join("build.rs"), render(BUILD_TEMPLATE))?;
+    fs::write(dir.join("src").join("lib.rs"), render(LIB_TEMPLATE))?;
+
+    for package in &RUNTIME_DEPENDENCIES {
+        let version = match *package {
+            "parity-scale-codec" => CODEC_VERSION,
+            _ => frame_version,
+        };
+        let options = PalletDependencyOptions {
+            registry,
+            ..PalletDependencyOptions::default()
+        };
+        add_pallet_to_manifest(
+            &manifest_path,
+            &Dependency::new(package).set_version(version),
+            &options,
+        )?;
+    }
+
+    let options = PalletDependencyOptions {
+        section: DependencySection {
+            kind: DependencyKind::Build,
+            target: None,
+        },
+        registry,
+        ..PalletDependencyOptions::default()
+    };
+    add_pallet_to_manifest(
+        &manifest_path,
+        &Dependency::new("substrate-wasm-builder-runner").set_version(WASM_BUILDER_RUNNER_VERSION),
+        &options,
+    )?;
+
+    Ok(manifest_path)
+}
@@ -0,0 +1,78 @@
+//! Manage the pallets of Substrate runtimes: add, remove & configure pallets, edit the runtime
+//! manifest & `lib.rs`, and generate the runtime dependency graph.
+//!
+//! Each command of the `substrate-deps` CLI is an `execute_*` function taking the path of the
+//! runtime manifest & typed options, e.g. to add pallets from a build script:
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use substrate_deps::{add::execute_add, spec::PalletSpec};
+//!
+//! # fn main() -> substrate_deps::error::CliResult<()> {
+//! let pallets = [
+//!     PalletSpec::parse("pallet-timestamp@2.0.0"),
+//!     PalletSpec {
+//!         alias: Some("council".to_owned()),
+//!         instance: Some(1),
+//!         ..PalletSpec::parse("pallet-collective")
+//!     },
+//! ];
+//! execute_add(Path::new("runtime/Cargo.toml"), &pallets, None)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The editors the commands are built on are exposed too: `manifest` for the runtime
+//! `Cargo.toml`, `runtime` for its `lib.rs`, and `toml_text` for any TOML document.
+
+#![warn(clippy::all)]
+
+#[macro_use]
+extern crate lazy_static;
+
+/// Add pallets to a runtime.
+pub mod add;
+/// Check the runtime pallet indices.
+pub mod check;
+/// Compare the pallets of two versions of a runtime.
+pub mod diff;
+/// Report the errors of the commands.
+pub mod error;
+/// Export the pallets of a runtime as a declaration.
+pub mod export;
+/// List, enable & disable the features of the runtime pallets.
+pub mod features;
+/// Edit the genesis config of the pallets in the node chain spec.
+pub mod genesis;
+/// Read files at a git revision.
+pub mod git;
+/// Generate the runtime dependency graph.
+pub mod graph;
+/// Generate a runtime crate.
+pub mod init_runtime;
+/// Edit the runtime manifest.
+pub mod manifest;
+/// Read the manifests & metadata of pallet crates.
+pub mod metadata;
+/// Generate a pallet crate.
+pub mod new_pallet;
+/// Print the status lines of the commands.
+pub mod output;
+/// Read the index of the registry.
+pub mod registry;
+/// Remove a pallet from a runtime.
+pub mod remove;
+/// Edit the runtime APIs & node RPC extensions of the pallets.
+pub mod rpc;
+/// Edit the runtime `lib.rs`.
+pub mod runtime;
+/// Parse the pallets to add from the command line & TOML files.
+pub mod spec;
+/// Converge a runtime to its declaration.
+pub mod sync;
+/// Edit TOML files preserving their formatting.
+pub mod toml_text;
+/// Restore the runtime & node files when a command fails.
+pub mod transaction;
+/// Find the runtime, node & workspace manifests.
+pub mod workspace;
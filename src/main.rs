@@ -1,20 +1,28 @@
 #![warn(clippy::all)]
 
-mod add;
-mod error;
-mod graph;
-mod manifest;
-mod metadata;
-mod registry;
-mod runtime;
-
-#[macro_use]
-extern crate lazy_static;
-
-use crate::manifest::find_manifest_file;
 use clap::{crate_description, crate_name, crate_version, App, Arg, ArgMatches, SubCommand};
-use log::{warn, LevelFilter};
-use std::env;
+use env_logger::fmt::Color;
+use log::{warn, Level, LevelFilter};
+use serde::Serialize;
+use std::{
+    env,
+    io::Write,
+    path::{Path, PathBuf},
+};
+use substrate_deps::{
+    add, check, diff,
+    error::{CliError, CliResult, MessageFormat},
+    export, features,
+    graph::{self, GraphFormat, GraphOptions},
+    init_runtime,
+    manifest::{find_manifest_file, DependencyKind, DependencySection},
+    new_pallet,
+    output::{self, Verbosity},
+    remove,
+    spec::{read_pallets_file, PalletSpec},
+    sync,
+    workspace::find_runtime_manifest,
+};
 
 fn parse_cli<'a>() -> ArgMatches<'a> {
     App::new(crate_name!())
@@ -24,17 +32,35 @@ fn parse_cli<'a>() -> ArgMatches<'a> {
             Arg::with_name("manifest-path")
                 .long("manifest-path")
                 .value_name("path")
-                .help("Path to the manifest of the runtime.")
+                .help("Path to the manifest of the runtime, or its directory.")
                 .takes_value(true)
                 .global(true)
                 .default_value("Cargo.toml"),
         )
+        .arg(
+            Arg::with_name("runtime")
+                .long("runtime")
+                .value_name("name")
+                .help("Name of the runtime crate to use when the manifest is a workspace.")
+                .takes_value(true)
+                .global(true),
+        )
         .arg(
             Arg::with_name("quiet")
                 .long("quiet")
                 .short("q")
                 .global(true)
-                .help("No output printed to stdout"),
+                .help("Print nothing but errors"),
+        )
+        .arg(
+            Arg::with_name("message-format")
+                .long("message-format")
+                .value_name("format")
+                .help("Format of the messages, json printing the results of the commands, and errors, as JSON objects to stdout")
+                .takes_value(true)
+                .possible_values(&["human", "json"])
+                .default_value("human")
+                .global(true),
         )
         .arg(
             Arg::with_name("v")
@@ -48,13 +74,30 @@ fn parse_cli<'a>() -> ArgMatches<'a> {
         // offline, locked, no-default-features, etc
         .subcommand(
             SubCommand::with_name("add")
-                .about("Adds a pallet to the Substrate runtime.")
+                .about("Adds pallets to the Substrate runtime.")
                 .arg(
                     Arg::with_name("pallet")
-                        .help("Pallet to be added e.g. pallet-staking")
-                        .required(true)
+                        .help("Pallets to be added e.g. pallet-staking, optionally with an exact version e.g. pallet-staking@2.0.0")
+                        .required_unless("file")
+                        .multiple(true)
                         .index(1),
                 )
+                .arg(
+                    Arg::with_name("file")
+                        .long("file")
+                        .short("f")
+                        .value_name("path")
+                        .help("TOML file describing the pallets to be added in its [pallets] table")
+                        .takes_value(true)
+                        .conflicts_with_all(&["pallet", "alias", "instance", "index", "features", "with-rpc", "dev", "build", "target", "path"])
+                )
+                .arg(
+                    Arg::with_name("path")
+                        .long("path")
+                        .value_name("path")
+                        .help("Directory of the local pallet crate to add, instead of a registry crate")
+                        .takes_value(true)
+                )
                 .arg(
                     Arg::with_name("alias")
                         .long("alias")
@@ -62,15 +105,282 @@ fn parse_cli<'a>() -> ArgMatches<'a> {
                         .help("Alias to be used in code & config e.g. staking instead of pallet-staking")
                         .takes_value(true)
                 )
+                .arg(
+                    Arg::with_name("instance")
+                        .long("instance")
+                        .value_name("n")
+                        .help("Add instance n of an instantiable pallet e.g. 1 for Instance1")
+                        .takes_value(true)
+                )
+                .arg(
+                    Arg::with_name("index")
+                        .long("index")
+                        .value_name("n")
+                        .help("Index of the pallet in the runtime, defaults to the next free index")
+                        .takes_value(true)
+                )
+                .arg(
+                    Arg::with_name("features")
+                        .long("features")
+                        .value_name("features")
+                        .help("Comma separated list of pallet features to enable e.g. historical")
+                        .takes_value(true)
+                        .use_delimiter(true)
+                )
+                .arg(
+                    Arg::with_name("via")
+                        .long("via")
+                        .value_name("feature")
+                        .help("Runtime feature to forward the pallet features through, instead of enabling them unconditionally")
+                        .takes_value(true)
+                        .requires("features")
+                )
+                .arg(
+                    Arg::with_name("with-rpc")
+                        .long("with-rpc")
+                        .help("Also add the pallet runtime API & RPC extension, if it ships them")
+                )
+                .arg(
+                    Arg::with_name("dev")
+                        .long("dev")
+                        .help("Add the pallet to the dev-dependencies e.g. a mock pallet for tests, without updating the runtime code")
+                        .conflicts_with_all(&["build", "instance", "index", "via", "with-rpc"])
+                )
+                .arg(
+                    Arg::with_name("build")
+                        .long("build")
+                        .help("Add the pallet to the build-dependencies, without updating the runtime code")
+                        .conflicts_with_all(&["instance", "index", "via", "with-rpc"])
+                )
+                .arg(
+                    Arg::with_name("target")
+                        .long("target")
+                        .value_name("cfg")
+                        .help("Add the pallet as a target specific dependency e.g. 'cfg(feature = \"std\")'")
+                        .takes_value(true)
+                )
                 .arg(
             Arg::with_name("registry")
                 .long("registry")
                 .value_name("registry")
                 .help("Registry to use")
                 .takes_value(true)
-                .global(true)
         )
         )
+        .subcommand(
+            SubCommand::with_name("remove")
+                .about("Removes a pallet from the Substrate runtime.")
+                .arg(
+                    Arg::with_name("pallet")
+                        .help("Pallet to be removed e.g. pallet-staking")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("alias")
+                        .long("alias")
+                        .short("a")
+                        .help("Alias the pallet was added with e.g. staking instead of pallet-staking")
+                        .takes_value(true)
+                )
+                .arg(
+                    Arg::with_name("instance")
+                        .long("instance")
+                        .value_name("n")
+                        .help("Remove instance n of an instantiable pallet e.g. 1 for Instance1")
+                        .takes_value(true)
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("features")
+                .about("Lists, enables or disables the features of a pallet of the Substrate runtime.")
+                .arg(
+                    Arg::with_name("pallet")
+                        .help("Pallet whose features to manage e.g. pallet-session")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("enable")
+                        .long("enable")
+                        .value_name("features")
+                        .help("Comma separated list of pallet features to enable e.g. historical")
+                        .takes_value(true)
+                        .use_delimiter(true)
+                )
+                .arg(
+                    Arg::with_name("disable")
+                        .long("disable")
+                        .value_name("features")
+                        .help("Comma separated list of pallet features to disable")
+                        .takes_value(true)
+                        .use_delimiter(true)
+                )
+                .arg(
+                    Arg::with_name("via")
+                        .long("via")
+                        .value_name("feature")
+                        .help("Runtime feature to forward the enabled features through, instead of enabling them unconditionally")
+                        .takes_value(true)
+                        .requires("enable")
+                )
+                .arg(
+                    Arg::with_name("registry")
+                        .long("registry")
+                        .value_name("registry")
+                        .help("Registry to use")
+                        .takes_value(true)
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("init-runtime")
+                .about("Creates a new Substrate runtime crate with the given pallets.")
+                .arg(
+                    Arg::with_name("pallet")
+                        .help("Pallets to be added e.g. pallet-balances, optionally with an exact version e.g. pallet-balances@2.0.0")
+                        .multiple(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("file")
+                        .long("file")
+                        .short("f")
+                        .value_name("path")
+                        .help("TOML file describing the pallets to be added in its [pallets] table")
+                        .takes_value(true)
+                        .conflicts_with("pallet")
+                )
+                .arg(
+                    Arg::with_name("path")
+                        .long("path")
+                        .value_name("path")
+                        .help("Directory of the runtime crate")
+                        .takes_value(true)
+                        .default_value("runtime")
+                )
+                .arg(
+                    Arg::with_name("name")
+                        .long("name")
+                        .value_name("name")
+                        .help("Name of the runtime crate")
+                        .takes_value(true)
+                        .default_value("node-runtime")
+                )
+                .arg(
+                    Arg::with_name("frame-version")
+                        .long("frame-version")
+                        .value_name("version")
+                        .help("Version of the FRAME crates & of the pallets without an exact version, in 2.x")
+                        .takes_value(true)
+                        .default_value(init_runtime::FRAME_VERSION)
+                )
+                .arg(
+                    Arg::with_name("registry")
+                        .long("registry")
+                        .value_name("registry")
+                        .help("Registry to use")
+                        .takes_value(true)
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("new-pallet")
+                .about("Creates a new pallet crate in the workspace of the Substrate runtime.")
+                .arg(
+                    Arg::with_name("name")
+                        .help("Name of the pallet crate e.g. pallet-kitties")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("path")
+                        .long("path")
+                        .value_name("path")
+                        .help("Directory of the pallet crate, defaults to pallets/<name> in the workspace e.g. pallets/kitties")
+                        .takes_value(true)
+                )
+                .arg(
+                    Arg::with_name("add")
+                        .long("add")
+                        .help("Also add the pallet to the runtime")
+                )
+                .arg(
+                    Arg::with_name("registry")
+                        .long("registry")
+                        .value_name("registry")
+                        .help("Registry to use")
+                        .takes_value(true)
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("sync")
+                .about("Adds, updates or removes pallets to converge the Substrate runtime to its declaration.")
+                .arg(
+                    Arg::with_name("file")
+                        .long("file")
+                        .short("f")
+                        .value_name("path")
+                        .help("TOML file declaring the runtime pallets in its [pallets] table, defaults to runtime.toml next to the runtime manifest or to its [package.metadata.substrate-deps.pallets]")
+                        .takes_value(true)
+                )
+                .arg(
+                    Arg::with_name("dry-run")
+                        .long("dry-run")
+                        .help("Only print the changes, without applying them")
+                )
+                .arg(
+                    Arg::with_name("registry")
+                        .long("registry")
+                        .value_name("registry")
+                        .help("Registry to use")
+                        .takes_value(true)
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("Exports the pallets of the Substrate runtime as a declaration, which sync can converge to.")
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .value_name("format")
+                        .help("Format of the exported declaration")
+                        .takes_value(true)
+                        .possible_values(&["toml", "json"])
+                        .default_value("toml")
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .short("o")
+                        .value_name("path")
+                        .help("File to write the declaration to, instead of stdout")
+                        .takes_value(true)
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("diff")
+                .about("Compare the pallets of two versions of the Substrate runtime.")
+                .arg(
+                    Arg::with_name("old")
+                        .help("Git revision, runtime manifest or runtime crate directory to compare from")
+                        .required(true)
+                )
+                .arg(
+                    Arg::with_name("new")
+                        .help("Git revision, runtime manifest or runtime crate directory to compare to, defaults to the working tree")
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("check")
+                .about("Check the runtime pallet indices for collisions & changes against a git revision.")
+                .arg(
+                    Arg::with_name("rev")
+                        .long("rev")
+                        .value_name("rev")
+                        .help("Git revision to compare the runtime pallet indices with")
+                        .takes_value(true)
+                        .default_value("HEAD")
+                )
+        )
         .subcommand(
             SubCommand::with_name("graph")
                 .about("Generate a graph of the Substrate runtime pallet dependencies.")
@@ -84,47 +394,249 @@ fn parse_cli<'a>() -> ArgMatches<'a> {
         .get_matches()
 }
 
-fn config_log(m: &ArgMatches) {
-    let log_level = if m.is_present("quiet") {
-        LevelFilter::Error
+fn verbosity(m: &ArgMatches) -> Verbosity {
+    if m.is_present("quiet") {
+        Verbosity::Quiet
+    } else if m.occurrences_of("v") > 0 {
+        Verbosity::Verbose
     } else {
-        match m.occurrences_of("v") {
-            0 => LevelFilter::Info,
-            1 => LevelFilter::Debug,
-            2 | _ => LevelFilter::Trace,
-        }
+        Verbosity::Normal
+    }
+}
+
+fn config_log(m: &ArgMatches, verbosity: Verbosity) {
+    let log_level = match verbosity {
+        // Errors are reported by main, RUST_LOG can't make quiet runs print anything else
+        Verbosity::Quiet => LevelFilter::Off,
+        Verbosity::Normal => LevelFilter::Warn,
+        Verbosity::Verbose if m.occurrences_of("v") == 1 => LevelFilter::Debug,
+        Verbosity::Verbose => LevelFilter::Trace,
     };
-    env_logger::from_env(env_logger::Env::default().default_filter_or(format!(
-        "{}={}",
-        crate_name!().replace("-", "_"),
-        log_level
-    )))
-    .format_timestamp(None)
-    .format_level(false)
-    .format_module_path(false)
-    .init();
+    let mut builder = if verbosity == Verbosity::Quiet {
+        let mut builder = env_logger::Builder::new();
+        builder.filter_level(log_level);
+        builder
+    } else {
+        env_logger::from_env(env_logger::Env::default().default_filter_or(format!(
+            "{}={}",
+            crate_name!().replace("-", "_"),
+            log_level
+        )))
+    };
+    builder
+        .format(|buf, record| {
+            // Warnings are prefixed like cargo's, the other messages are printed as is
+            if record.level() == Level::Warn {
+                let mut style = buf.style();
+                style.set_color(Color::Yellow).set_bold(true);
+                writeln!(buf, "{} {}", style.value("warning:"), record.args())
+            } else {
+                writeln!(buf, "{}", record.args())
+            }
+        })
+        .init();
 }
 
 fn main() {
     let m = parse_cli();
-    config_log(&m);
+    let format = match m.value_of("message-format") {
+        Some("json") => MessageFormat::Json,
+        _ => MessageFormat::Human,
+    };
+    let verbosity = verbosity(&m);
+    output::set_verbosity(verbosity);
+    // The messages for humans would repeat the JSON output
+    output::set_messages(format == MessageFormat::Human);
+    config_log(&m, verbosity);
+
+    if let Err(err) = run(&m, format) {
+        err.exit_with(format)
+    }
+}
+
+fn run(m: &ArgMatches, format: MessageFormat) -> CliResult<()> {
+    // The runtime crate doesn't exist yet, there is no runtime manifest to look for
+    if let ("init-runtime", Some(m)) = m.subcommand() {
+        let pallets = match m.value_of("file") {
+            Some(file) => read_pallets_file(Path::new(file))?,
+            None => m
+                .values_of("pallet")
+                .map(|pallets| pallets.map(PalletSpec::parse).collect())
+                .unwrap_or_default(),
+        };
+        let path = Path::new(m.value_of("path").unwrap()); // path has a default value so we can safely unwrap
+        let name = m.value_of("name").unwrap(); // name has a default value so we can safely unwrap
+        let frame_version = m.value_of("frame-version").unwrap(); // frame-version has a default value so we can safely unwrap
+        let registry = m.value_of("registry");
+        return init_runtime::execute_init_runtime(path, name, &pallets, frame_version, registry);
+    }
 
     let manifest = m.value_of("manifest-path").unwrap(); // manifest-path has a default value so we can safely unwrap
-    let manifest_path = find_manifest_file(manifest).unwrap(); // -> Stop on error, if any
+    let manifest_path = find_manifest_file(manifest)?;
+    let runtime = m.value_of("runtime");
+    let manifest_path = find_runtime_manifest(&manifest_path, runtime)?;
 
-    if let Err(err) = match m.subcommand() {
+    match m.subcommand() {
         ("add", Some(m)) => {
             //TODO: move to config.rs
+            let pallets = match m.value_of("file") {
+                Some(file) => read_pallets_file(Path::new(file))?,
+                None => pallet_specs(m)?,
+            };
+            let registry = m.value_of("registry");
+            //TODO: should get (local registry path, registry uri)
+            let added = add::execute_add(&manifest_path, &pallets, registry)?;
+            print_reports(format, "pallet-added", &added)
+        }
+        ("remove", Some(m)) => {
             let pallet = m.value_of("pallet").unwrap(); // pallet arg is required so we can safely unwrap
             let alias = m.value_of("alias");
+            let instance = m
+                .value_of("instance")
+                .map(str::parse)
+                .transpose()
+                .map_err(|_| CliError::Generic("Invalid pallet instance.".to_owned()))?;
+            let removed = remove::execute_remove(&manifest_path, pallet, alias, instance)?;
+            print_reports(format, "pallet-removed", &[removed])
+        }
+        ("features", Some(m)) => {
+            let pallet = m.value_of("pallet").unwrap(); // pallet arg is required so we can safely unwrap
+            let enable = values_of(m, "enable");
+            let disable = values_of(m, "disable");
+            let via = m.value_of("via");
             let registry = m.value_of("registry");
-            //TODO: should get (local registry path, registry uri)
-            add::execute_add(&manifest_path, pallet, alias, registry)
+            features::execute_features(
+                &manifest_path,
+                pallet,
+                &enable,
+                &disable,
+                via,
+                registry,
+                format,
+            )
+        }
+        ("new-pallet", Some(m)) => {
+            let name = m.value_of("name").unwrap(); // name arg is required so we can safely unwrap
+            let path = m.value_of("path").map(Path::new);
+            let add = m.is_present("add");
+            let registry = m.value_of("registry");
+            new_pallet::execute_new_pallet(&manifest_path, name, path, add, registry)
+        }
+        ("sync", Some(m)) => {
+            let file = m.value_of("file").map(Path::new);
+            let dry_run = m.is_present("dry-run");
+            let registry = m.value_of("registry");
+            let changes = sync::execute_sync(&manifest_path, file, dry_run, registry)?;
+            print_reports(format, "sync-change", &changes)
+        }
+        ("export", Some(m)) => {
+            let format = match m.value_of("format") {
+                Some("json") => export::ExportFormat::Json,
+                _ => export::ExportFormat::Toml,
+            };
+            let output = m.value_of("output").map(Path::new);
+            export::execute_export(&manifest_path, format, output)
+        }
+        ("diff", Some(m)) => {
+            let old = m.value_of("old").unwrap(); // old arg is required so we can safely unwrap
+            let new = m.value_of("new");
+            diff::execute_diff(&manifest_path, old, new, runtime, format)
+        }
+        ("check", Some(m)) => {
+            let rev = m.value_of("rev").unwrap(); // rev has a default value so we can safely unwrap
+            check::execute_check(&manifest_path, rev, format)
+        }
+        ("graph", Some(m)) => {
+            let options = GraphOptions {
+                include_versions: m.is_present("include-versions"),
+                format: match format {
+                    MessageFormat::Json => GraphFormat::Json,
+                    MessageFormat::Human => GraphFormat::Dot,
+                },
+            };
+            graph::execute_graph(&manifest_path, &options)
         }
-        ("graph", Some(m)) => graph::execute_graph(&m),
         _ => Ok(()),
-    } {
-        eprintln!("{}", err);
-        std::process::exit(1);
     }
 }
+
+/// Print each of the `reports` of a command as a JSON object tagged with the `reason`, with the
+/// JSON `format`.
+fn print_reports(format: MessageFormat, reason: &str, reports: &[impl Serialize]) -> CliResult<()> {
+    if format == MessageFormat::Human {
+        return Ok(());
+    }
+    for report in reports {
+        let mut message = serde_json::to_value(report)
+            .map_err(|e| CliError::Generic(format!("Error reporting {}: {}", reason, e)))?;
+        message["reason"] = reason.into();
+        println!("{}", message);
+    }
+    Ok(())
+}
+
+/// Build the specs of the pallets given on the command line of `add`.
+fn pallet_specs(m: &ArgMatches) -> CliResult<Vec<PalletSpec>> {
+    let pallets = m.values_of("pallet").unwrap().collect::<Vec<_>>(); // pallet arg is required without file so we can safely unwrap
+    if pallets.len() > 1
+        && ["alias", "instance", "index", "features", "path"]
+            .iter()
+            .any(|arg| m.is_present(arg))
+    {
+        return Err(CliError::Generic(
+            "--alias, --instance, --index, --features & --path can only be used to add a single pallet."
+                .to_owned(),
+        ));
+    }
+
+    let alias = m.value_of("alias");
+    let instance = m
+        .value_of("instance")
+        .map(str::parse)
+        .transpose()
+        .map_err(|_| CliError::Generic("Invalid pallet instance.".to_owned()))?;
+    let index = m
+        .value_of("index")
+        .map(str::parse)
+        .transpose()
+        .map_err(|_| CliError::Generic("Invalid pallet index.".to_owned()))?;
+    let section = DependencySection {
+        kind: if m.is_present("dev") {
+            DependencyKind::Development
+        } else if m.is_present("build") {
+            DependencyKind::Build
+        } else {
+            DependencyKind::Normal
+        },
+        target: m.value_of("target").map(str::to_owned),
+    };
+
+    let path = m.value_of("path").map(PathBuf::from);
+    if path.is_some() && pallets.iter().any(|pallet| pallet.contains('@')) {
+        return Err(CliError::Generic(
+            "The version of a local pallet is the version of its crate.".to_owned(),
+        ));
+    }
+
+    Ok(pallets
+        .into_iter()
+        .map(|pallet| PalletSpec {
+            path: path.clone(),
+            alias: alias.map(str::to_owned),
+            instance,
+            index,
+            features: values_of(m, "features"),
+            via: m.value_of("via").map(str::to_owned),
+            with_rpc: m.is_present("with-rpc"),
+            section: section.clone(),
+            ..PalletSpec::parse(pallet)
+        })
+        .collect())
+}
+
+/// Collect the values of the comma separated list argument `name`.
+fn values_of(m: &ArgMatches, name: &str) -> Vec<String> {
+    m.values_of(name)
+        .map(|values| values.map(str::to_owned).collect())
+        .unwrap_or_default()
+}
@@ -1,4 +1,6 @@
 use crate::error::{CliError, CliResult};
+use crate::toml_text::{TomlText, Value};
+use crate::workspace::find_workspace_root;
 
 use std::{
     env,
@@ -6,13 +8,78 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use cargo_edit::{Dependency, Manifest};
+use cargo_edit::Dependency;
+use log::debug;
+
+/// Kind of a dependency, i.e. the dependency table declaring it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DependencyKind {
+    /// `[dependencies]`, the dependencies of the runtime code.
+    Normal,
+    /// `[dev-dependencies]` e.g. mock pallets for tests.
+    Development,
+    /// `[build-dependencies]`.
+    Build,
+}
+
+/// Section of the manifest declaring a dependency e.g. `[target.'cfg(unix)'.dev-dependencies]`.
+#[derive(Clone, Debug)]
+pub struct DependencySection {
+    pub kind: DependencyKind,
+    /// Target platform of the dependency, if it is target specific.
+    pub target: Option<String>,
+}
+
+impl Default for DependencySection {
+    fn default() -> Self {
+        Self {
+            kind: DependencyKind::Normal,
+            target: None,
+        }
+    }
+}
+
+impl DependencySection {
+    /// Path of the dependency table in the manifest.
+    pub fn path(&self) -> Vec<&str> {
+        let table = match self.kind {
+            DependencyKind::Normal => "dependencies",
+            DependencyKind::Development => "dev-dependencies",
+            DependencyKind::Build => "build-dependencies",
+        };
+        match &self.target {
+            Some(target) => vec!["target", target, table],
+            None => vec![table],
+        }
+    }
+
+    /// Whether the runtime code uses the dependency, i.e. it isn't a dev or build dependency.
+    pub fn is_runtime(&self) -> bool {
+        self.kind == DependencyKind::Normal
+    }
+}
 
 pub fn find_manifest_file(file: &str) -> CliResult<PathBuf> {
     let pwd = env::current_dir()?;
-    let manifest = pwd.join(file);
-    let file_name = manifest.file_name().unwrap();
-    let mut dir = manifest.parent().unwrap().to_path_buf();
+    let mut manifest = pwd.join(file);
+    // A directory stands for the manifest it contains
+    if manifest.is_dir() {
+        manifest = manifest.join("Cargo.toml");
+    }
+    let (file_name, start_dir) = match (manifest.file_name(), manifest.parent()) {
+        (Some(file_name), Some(dir)) => (file_name, dir),
+        _ => {
+            return Err(CliError::Manifest(format!(
+                "{:?} is not the path of a manifest.",
+                file
+            )))
+        }
+    };
+    // Search the actual parent directories, rather than the parents of a path with `..` in it
+    let start_dir = start_dir
+        .canonicalize()
+        .unwrap_or_else(|_| start_dir.to_path_buf());
+    let mut dir = start_dir.clone();
     let mut first_try = true;
 
     loop {
@@ -21,7 +88,7 @@ pub fn find_manifest_file(file: &str) -> CliResult<PathBuf> {
         if let Ok(metadata) = fs::metadata(&try_manifest) {
             if metadata.is_file() {
                 if !first_try {
-                    eprintln!("Found {:?} in {:?}.", file_name, dir.display());
+                    debug!("Found {:?} in {:?}.", file_name, dir.display());
                 }
 
                 return Ok(try_manifest);
@@ -29,7 +96,7 @@ pub fn find_manifest_file(file: &str) -> CliResult<PathBuf> {
         }
 
         if first_try {
-            eprintln!(
+            debug!(
                 "Could not find {:?} in {:?}, searching parent directories.",
                 file_name,
                 dir.display()
@@ -39,10 +106,9 @@ pub fn find_manifest_file(file: &str) -> CliResult<PathBuf> {
 
         dir = match dir.parent() {
             None => {
-                return Err(CliError::Generic(format!(
+                return Err(CliError::Manifest(format!(
                     "Could not find {:?} in {:?} or any parent directory",
-                    file,
-                    manifest.parent().unwrap()
+                    file_name, start_dir
                 )));
             }
             Some(ref dir) => dir.to_path_buf(),
@@ -50,96 +116,560 @@ pub fn find_manifest_file(file: &str) -> CliResult<PathBuf> {
     }
 }
 
+/// How a pallet dependency is added to the runtime manifest.
+#[derive(Clone, Debug, Default)]
+pub struct PalletDependencyOptions<'a> {
+    /// Alias of the pallet, naming its dependency.
+    pub alias: Option<&'a str>,
+    /// Instance of the pallet, whose instances share a single dependency.
+    pub instance: Option<u32>,
+    /// Section of the manifest declaring the dependency.
+    pub section: DependencySection,
+    /// Registry of the pallet, crates.io if `None`.
+    pub registry: Option<&'a str>,
+    /// Directory of the local pallet crate.
+    pub path: Option<&'a Path>,
+    /// Features exposed by the pallet, forwarded by the runtime features of the same name, if
+    /// known. Pallets whose features are unknown are expected to expose `std`.
+    pub pallet_features: Option<&'a [String]>,
+    /// Features enabled in the dependency, for dev & build dependencies.
+    pub enabled_features: &'a [String],
+}
+
 pub fn add_pallet_to_manifest(
     manifest_path: &Path,
     dependency: &Dependency,
-    alias: &Option<&str>,
-    registry: Option<&str>,
+    options: &PalletDependencyOptions,
 ) -> CliResult<()> {
-    // Open TOML manifest
-    let mut manifest = Manifest::open(&Some(manifest_path.to_path_buf()))
-        .map_err(|e| CliError::Manifest(e.to_string()))?;
+    // Instances of a pallet share a single dependency, named after the pallet
+    let alias = match options.instance {
+        Some(_) if find_dependency_key(manifest_path, &dependency.name)?.is_some() => return Ok(()),
+        Some(_) => &None,
+        None => &options.alias,
+    };
+
+    let name = &dependency_key(pallet_alias(dependency, alias));
+    let section = &options.section;
+    let source = (options.registry, options.path);
+
+    edit_manifest_text(manifest_path, |text| {
+        // Dev & build dependencies are std only, and not part of the runtime features
+        if !section.is_runtime() {
+            let mut fields =
+                pallet_dependency_fields(dependency.version(), &dependency.name, source, true);
+            if !options.enabled_features.is_empty() {
+                fields.push(("features", Value::Array(options.enabled_features.to_vec())));
+            }
+            return insert_dependency(manifest_path, text, (&section.path(), name), fields, true);
+        }
+
+        // Add pallet dependency
+        let fields =
+            pallet_dependency_fields(dependency.version(), &dependency.name, source, false);
+        insert_dependency(manifest_path, text, (&section.path(), name), fields, false)?;
+
+        // Add pallet/std to features table if the pallet has a std feature, and propagate the
+        // other pallet features to the runtime features of the same name e.g. runtime-benchmarks
+        // or try-runtime
+        // The std feature array is created if missing, but can't be extended if it isn't an array
+        let features = options.pallet_features;
+        let has_std = features.map_or(true, |features| features.iter().any(|f| f == "std"));
+        if has_std
+            && !text.insert_into_array(&["features", "std"], &format!("{}/std", name))
+            && text.array(&["features", "std"]).is_none()
+        {
+            return Err(CliError::FeatureNotArray {
+                feature: "std".to_owned(),
+                file: manifest_path.to_path_buf(),
+            });
+        }
+        for feature in features.unwrap_or_default() {
+            if feature != "std" && text.array(&["features", feature]).is_some() {
+                text.insert_into_array(&["features", feature], &format!("{}/{}", name, feature));
+            }
+        }
+        Ok(())
+    })
+}
 
-    let name = &inflector::cases::camelcase::to_camel_case(pallet_alias(dependency, alias));
+/// Add `dependency` with its default features to the manifest of a `std` only crate, such as the
+/// node. Returns the key of the dependency.
+pub fn add_dependency_to_manifest(
+    manifest_path: &Path,
+    dependency: &Dependency,
+    registry: Option<&str>,
+) -> CliResult<String> {
+    if let Some(key) = find_dependency_key(manifest_path, &dependency.name)? {
+        return Ok(key);
+    }
 
-    // Generate TOML table for pallet dependency
-    let dep_toml = pallet_dependency_to_toml(
-        name,
-        dependency.version().unwrap(),
-        &dependency.name,
-        registry,
-    );
+    let name = dependency_key(&dependency.name);
+    edit_manifest_text(manifest_path, |text| {
+        let fields = pallet_dependency_fields(
+            dependency.version(),
+            &dependency.name,
+            (registry, None),
+            true,
+        );
+        insert_dependency(
+            manifest_path,
+            text,
+            (&["dependencies"], &name),
+            fields,
+            true,
+        )
+    })?;
 
-    // Add pallet TOML table to dependencies table
-    insert_into_table(&mut manifest, &["dependencies".to_owned()], dep_toml)?;
+    Ok(name)
+}
 
-    // Add pallet/std to features table
-    let feature = format!("{}/std", name);
-    insert_into_array(&mut manifest, &["features".to_owned()], "std", feature)?;
+/// Key of the dependency on the crate `name`, which is also its path in Rust code e.g.
+/// `pallet_balances` for `pallet-balances`.
+pub fn dependency_key(name: &str) -> String {
+    inflector::cases::snakecase::to_snake_case(name)
+}
 
-    // Write modified TOML manifest
-    Manifest::find_file(&Some(manifest_path.to_path_buf()))
-        .and_then(|mut file| manifest.write_to_file(&mut file))
-        .map_err(|e| CliError::Manifest(e.to_string()))?;
+/// Path in Rust code of the dependency `key` e.g. `pallet_balances` for `pallet-balances`.
+pub fn crate_path(key: &str) -> String {
+    key.replace('-', "_")
+}
+
+/// Remove the dependency `key` and its features from the runtime manifest.
+pub fn remove_pallet_from_manifest(manifest_path: &Path, key: &str) -> CliResult<()> {
+    edit_manifest_text(manifest_path, |text| {
+        let table = dependency_table(text, key).unwrap_or_else(|| vec!["dependencies".to_owned()]);
+        let table = table.iter().map(String::as_str).collect::<Vec<_>>();
+        if !text.remove(&[&table[..], &[key]].concat()) {
+            return Err(CliError::Manifest(format!(
+                "The dependency `{}` could not be found in `dependencies`.",
+                key
+            )));
+        }
+
+        // Remove pallet/std & the other pallet features from features table
+        let prefix = format!("{}/", key);
+        for feature in text.table_keys(&["features"]) {
+            text.remove_from_array(&["features", &feature], |entry| entry.starts_with(&prefix));
+        }
+        Ok(())
+    })
+}
+
+/// Find the key of the runtime dependency on the `package` crate, if any.
+pub fn find_dependency_key(manifest_path: &Path, package: &str) -> CliResult<Option<String>> {
+    Ok(dependency_packages(manifest_path)?
+        .into_iter()
+        .find(|(_, declared)| declared == package)
+        .map(|(key, _)| key))
+}
+
+/// Find the package of the runtime dependency `key`, if any.
+pub fn find_dependency_package(manifest_path: &Path, key: &str) -> CliResult<Option<String>> {
+    Ok(dependency_packages(manifest_path)?
+        .into_iter()
+        .find(|(declared, _)| declared == key)
+        .map(|(_, package)| package))
+}
+
+/// Find the package of the runtime dependency whose path in Rust code is `path`, e.g.
+/// `pallet-balances` for `pallet_balances`, if any.
+pub fn find_crate_package(manifest_path: &Path, path: &str) -> CliResult<Option<String>> {
+    // The dependency key of hand written manifests may use dashes
+    Ok(dependency_packages(manifest_path)?
+        .into_iter()
+        .find(|(key, _)| crate_path(key) == path)
+        .map(|(_, package)| package))
+}
+
+/// List the runtime dependencies, as `(key, package)` pairs.
+fn dependency_packages(manifest_path: &Path) -> CliResult<Vec<(String, String)>> {
+    let text = read_manifest_text(manifest_path)?;
+    let root = match find_workspace_root(manifest_path)? {
+        Some(root_path) => Some(read_manifest_text(&root_path)?),
+        None => None,
+    };
+
+    let mut packages = Vec::new();
+    for table in runtime_dependency_tables(&text) {
+        let table = table.iter().map(String::as_str).collect::<Vec<_>>();
+        for key in text.table_keys(&table) {
+            let path = [&table[..], &[&key]].concat();
+            // Inherited dependencies declare their package in the workspace manifest
+            let package = match &root {
+                Some(root) if text.boolean(&[&path[..], &["workspace"]].concat()) == Some(true) => {
+                    root.string(&["workspace", "dependencies", &key, "package"])
+                }
+                _ => text.string(&[&path[..], &["package"]].concat()),
+            };
+            packages.push((key.clone(), package.unwrap_or(key)));
+        }
+    }
+
+    Ok(packages)
+}
+
+/// List the paths of the tables declaring runtime dependencies, the target specific ones
+/// included.
+fn runtime_dependency_tables(text: &TomlText) -> Vec<Vec<String>> {
+    let mut tables = vec![vec!["dependencies".to_owned()]];
+    for target in text.table_keys(&["target"]) {
+        if text.contains(&["target", &target, "dependencies"]) {
+            tables.push(vec!["target".to_owned(), target, "dependencies".to_owned()]);
+        }
+    }
+    tables
+}
+
+/// Path of the table declaring the runtime dependency `key`, if any.
+fn dependency_table(text: &TomlText, key: &str) -> Option<Vec<String>> {
+    runtime_dependency_tables(text).into_iter().find(|table| {
+        let table = table.iter().map(String::as_str).collect::<Vec<_>>();
+        text.contains(&[&table[..], &[key]].concat())
+    })
+}
+
+/// Insert the dependency `key` with the `fields` in the `table` of the manifest `text`, or in the
+/// workspace manifest if the crate inherits its dependencies from the workspace.
+fn insert_dependency(
+    manifest_path: &Path,
+    text: &mut TomlText,
+    (table, key): (&[&str], &str),
+    mut fields: Vec<(&str, Value)>,
+    default_features: bool,
+) -> CliResult<()> {
+    match find_workspace_root(manifest_path)? {
+        // Declare the dependency once in the workspace and inherit it in the crate
+        Some(root_path) if inherits_workspace_dependencies(&root_path, text)? => {
+            debug!("Workspace dependencies declared in: {:?}", root_path);
+            relativize_path(&mut fields, &root_path)?;
+
+            // Only the version & source are shared, features are declared by each crate
+            let (shared, own): (Vec<_>, Vec<_>) = fields
+                .into_iter()
+                .partition(|(name, _)| *name != "features");
+            edit_manifest_text(&root_path, |root| {
+                root.insert_table(&["workspace", "dependencies"], key, &shared);
+                Ok(())
+            })?;
+
+            let mut fields = workspace_dependency_fields(default_features);
+            fields.extend(own);
+            text.insert_table(table, key, &fields);
+        }
+        // Add dependency to dependencies table
+        _ => {
+            relativize_path(&mut fields, manifest_path)?;
+            text.insert_table(table, key, &fields);
+        }
+    }
+
+    Ok(())
+}
 
+/// Make the `path` field of a dependency, if any, relative to the directory of the manifest at
+/// `manifest_path` declaring it.
+fn relativize_path(fields: &mut [(&str, Value)], manifest_path: &Path) -> CliResult<()> {
+    let dir = manifest_path
+        .canonicalize()?
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    for (name, value) in fields.iter_mut() {
+        if let ("path", Value::String(path)) = (*name, &value) {
+            let path = relative_path(&dir, &Path::new(path).canonicalize()?);
+            *value = Value::String(path.to_string_lossy().replace('\\', "/"));
+        }
+    }
     Ok(())
 }
 
-fn insert_into_table(
-    manifest: &mut Manifest,
-    table_path: &[String],
-    table_entry: (String, toml_edit::Table),
+/// Path of `path` relative to the directory `dir`, both absolute.
+pub fn relative_path(dir: &Path, path: &Path) -> PathBuf {
+    let common = dir
+        .components()
+        .zip(path.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut relative = PathBuf::new();
+    for _ in dir.components().skip(common) {
+        relative.push("..");
+    }
+    for component in path.components().skip(common) {
+        relative.push(component);
+    }
+    relative
+}
+
+/// Enable the features `enable` & disable the features `disable` of the runtime dependency `key`.
+///
+/// Enabled features are added to the `features` of the dependency, or forwarded through the
+/// runtime feature `via` if any, creating it if needed. Disabled features are removed from both.
+pub fn set_dependency_features(
+    manifest_path: &Path,
+    key: &str,
+    enable: &[String],
+    disable: &[String],
+    via: Option<&str>,
 ) -> CliResult<()> {
-    let (entry_name, entry_table) = table_entry;
-    let table = manifest
-        .get_table(table_path)
-        .map_err(|e| CliError::Manifest(e.to_string()))?;
-
-    if table[&entry_name].is_none() {
-        let entries = table.as_table_mut().ok_or_else(|| {
-            CliError::Manifest(format!(
-                "Error updating '{}' runtime manifest.",
-                table_path.first().unwrap()
-            ))
+    edit_manifest_text(manifest_path, |text| {
+        let table = dependency_table(text, key).ok_or_else(|| {
+            CliError::Manifest(format!("Dependency {} not found in runtime manifest.", key))
         })?;
+        let features_path = table
+            .iter()
+            .map(String::as_str)
+            .chain(vec![key, "features"])
+            .collect::<Vec<_>>();
 
-        let _ = entries
-            .entry(&entry_name)
-            .or_insert(toml_edit::Item::Table(entry_table));
-        entries.sort_values();
-    } /*else {
-          // update an existing entry
-          merge_dependencies(&mut table[&dep.name], dep);
-          if let Some(t) = table.as_inline_table_mut() {
-              t.fmt()
-          }
-      }*/
+        // Enable the features in the dependency, or forward them through the runtime feature
+        for feature in enable {
+            match via {
+                Some(via) => {
+                    text.insert_into_array(&["features", via], &format!("{}/{}", key, feature))
+                }
+                None => text.insert_into_array(&features_path, feature),
+            };
+        }
 
-    Ok(())
+        // Disable the features in the dependency & in the runtime features forwarding them
+        for feature in disable {
+            text.remove_from_array(&features_path, |entry| entry == feature);
+            if matches!(text.array(&features_path), Some(array) if array.is_empty()) {
+                text.remove(&features_path);
+            }
+            let forwarded = format!("{}/{}", key, feature);
+            for runtime_feature in text.table_keys(&["features"]) {
+                text.remove_from_array(&["features", &runtime_feature], |entry| entry == forwarded);
+            }
+        }
+
+        Ok(())
+    })
+}
+
+/// Features of a runtime dependency.
+pub struct DependencyFeatures {
+    /// Features enabled in the dependency.
+    pub enabled: Vec<String>,
+    /// Features forwarded through runtime features, as `(runtime feature, feature)` pairs.
+    pub forwarded: Vec<(String, String)>,
 }
 
-fn insert_into_array(
-    manifest: &mut Manifest,
-    table_path: &[String],
-    table_entry: &str,
-    array_entry: String,
+/// List the features of the runtime dependency `key`.
+pub fn dependency_features(manifest_path: &Path, key: &str) -> CliResult<DependencyFeatures> {
+    let text = read_manifest_text(manifest_path)?;
+
+    let enabled = dependency_table(&text, key)
+        .and_then(|table| {
+            let table = table.iter().map(String::as_str).collect::<Vec<_>>();
+            text.array(&[&table[..], &[key, "features"]].concat())
+        })
+        .unwrap_or_default();
+
+    let prefix = format!("{}/", key);
+    let mut forwarded = Vec::new();
+    for runtime_feature in text.table_keys(&["features"]) {
+        if let Some(array) = text.array(&["features", &runtime_feature]) {
+            forwarded.extend(
+                array
+                    .iter()
+                    .filter_map(|v| v.strip_prefix(&prefix))
+                    .map(|feature| (runtime_feature.clone(), feature.to_owned())),
+            );
+        }
+    }
+
+    Ok(DependencyFeatures { enabled, forwarded })
+}
+
+/// Find the version of the runtime dependency `key`, if it declares one.
+pub fn find_dependency_version(manifest_path: &Path, key: &str) -> CliResult<Option<String>> {
+    let (manifest_path, path) = dependency_declaration(manifest_path, key)?;
+    let text = read_manifest_text(&manifest_path)?;
+    let path = path.iter().map(String::as_str).collect::<Vec<_>>();
+
+    Ok(text
+        .string(&[&path[..], &["version"]].concat())
+        .or_else(|| text.string(&path))
+        .map(|version| version.trim_start_matches(['^', '=', '~']).to_owned()))
+}
+
+/// Set the version of the runtime dependency `key` to `version`, where it is declared.
+pub fn set_dependency_version(manifest_path: &Path, key: &str, version: &str) -> CliResult<()> {
+    let (manifest_path, path) = dependency_declaration(manifest_path, key)?;
+    let path = path.iter().map(String::as_str).collect::<Vec<_>>();
+
+    edit_manifest_text(&manifest_path, |text| {
+        let version_path = [&path[..], &["version"]].concat();
+        if text.string(&path).is_some() {
+            text.set_string(&path, version);
+        } else if text.string(&version_path).is_some() {
+            text.set_string(&version_path, version);
+        } else {
+            return Err(CliError::Manifest(format!(
+                "Dependency {} doesn't declare a version.",
+                key
+            )));
+        }
+        Ok(())
+    })
+}
+
+/// Describe the source of the runtime dependency `key`, if it is declared: `crates-io`,
+/// `registry+<name>`, `git+<url>` with its branch, tag or rev if any, or `path+<path>`.
+pub fn find_dependency_source(manifest_path: &Path, key: &str) -> CliResult<Option<String>> {
+    let text = read_manifest_text(manifest_path)?;
+    if dependency_table(&text, key).is_none() {
+        return Ok(None);
+    }
+    let (manifest_path, path) = dependency_declaration(manifest_path, key)?;
+    let text = read_manifest_text(&manifest_path)?;
+    let path = path.iter().map(String::as_str).collect::<Vec<_>>();
+    let field = |name: &str| text.string(&[&path[..], &[name]].concat());
+
+    Ok(Some(if let Some(git) = field("git") {
+        let reference = ["branch", "tag", "rev"]
+            .iter()
+            .find_map(|name| field(name).map(|value| format!("?{}={}", name, value)))
+            .unwrap_or_default();
+        format!("git+{}{}", git, reference)
+    } else if let Some(path) = field("path") {
+        format!("path+{}", path)
+    } else if let Some(registry) = field("registry") {
+        format!("registry+{}", registry)
+    } else {
+        "crates-io".to_owned()
+    }))
+}
+
+/// Directory of the runtime dependency `key`, if it is a path dependency.
+pub fn find_dependency_path(manifest_path: &Path, key: &str) -> CliResult<Option<PathBuf>> {
+    let (declaration_path, path) = dependency_declaration(manifest_path, key)?;
+    let text = read_manifest_text(&declaration_path)?;
+    let path = path.iter().map(String::as_str).collect::<Vec<_>>();
+    let declaration_dir = declaration_path.parent().unwrap_or_else(|| Path::new("."));
+    Ok(text
+        .string(&[&path[..], &["path"]].concat())
+        .map(|path| declaration_dir.join(path)))
+}
+
+/// Version & source fields of the runtime dependency `key` e.g. `version`, or `git` & `branch`, to
+/// declare the same dependency in the crate in the directory `dir`, its `path` if any being made
+/// relative to `dir`.
+pub fn dependency_source_fields(
+    manifest_path: &Path,
+    key: &str,
+    dir: &Path,
+) -> CliResult<Vec<(&'static str, Value)>> {
+    let (declaration_path, path) = dependency_declaration(manifest_path, key)?;
+    let text = read_manifest_text(&declaration_path)?;
+    let path = path.iter().map(String::as_str).collect::<Vec<_>>();
+    if let Some(version) = text.string(&path) {
+        return Ok(vec![("version", Value::String(version))]);
+    }
+
+    let mut fields = Vec::new();
+    for name in &["version", "git", "branch", "tag", "rev", "path", "registry"] {
+        let value = match text.string(&[&path[..], &[*name]].concat()) {
+            Some(value) => value,
+            None => continue,
+        };
+        let value = if *name == "path" {
+            let declaration_dir = declaration_path.parent().unwrap_or_else(|| Path::new("."));
+            relative_path(
+                &dir.canonicalize()?,
+                &declaration_dir.join(value).canonicalize()?,
+            )
+            .to_string_lossy()
+            .replace('\\', "/")
+        } else {
+            value
+        };
+        fields.push((*name, Value::String(value)));
+    }
+
+    Ok(fields)
+}
+
+/// Manifest & path of the table declaring the version & source of the runtime dependency `key`,
+/// i.e. the workspace manifest for dependencies inherited from the workspace.
+fn dependency_declaration(manifest_path: &Path, key: &str) -> CliResult<(PathBuf, Vec<String>)> {
+    let text = read_manifest_text(manifest_path)?;
+    let path = dependency_table(&text, key)
+        .unwrap_or_else(|| vec!["dependencies".to_owned()])
+        .into_iter()
+        .chain(Some(key.to_owned()))
+        .collect::<Vec<_>>();
+    let workspace = [path.iter().map(String::as_str).collect(), vec!["workspace"]].concat();
+    if text.boolean(&workspace) == Some(true) {
+        if let Some(root_path) = find_workspace_root(manifest_path)? {
+            let path = vec![
+                "workspace".to_owned(),
+                "dependencies".to_owned(),
+                key.to_owned(),
+            ];
+            return Ok((root_path, path));
+        }
+    }
+
+    Ok((manifest_path.to_path_buf(), path))
+}
+
+/// Whether the manifest declares the dependency `key` in `section`.
+pub fn contains_dependency(
+    manifest_path: &Path,
+    section: &DependencySection,
+    key: &str,
+) -> CliResult<bool> {
+    let text = read_manifest_text(manifest_path)?;
+    Ok(text.contains(&[&section.path()[..], &[key]].concat()))
+}
+
+/// Whether pallet dependencies should be declared in `[workspace.dependencies]` of the manifest
+/// `root_path` of the workspace of `manifest`, i.e. if it has such a table and `manifest` already
+/// inherits dependencies from it.
+fn inherits_workspace_dependencies(root_path: &Path, manifest: &TomlText) -> CliResult<bool> {
+    let root = read_manifest_text(root_path)?;
+    if !root.contains(&["workspace", "dependencies"]) {
+        return Ok(false);
+    }
+
+    Ok(["dependencies", "dev-dependencies", "build-dependencies"]
+        .iter()
+        .any(|section| {
+            manifest
+                .table_keys(&[section])
+                .iter()
+                .any(|key| manifest.boolean(&[section, key, "workspace"]) == Some(true))
+        }))
+}
+
+fn read_manifest_text(manifest_path: &Path) -> CliResult<TomlText> {
+    fs::read_to_string(manifest_path)
+        .map_err(CliError::from)
+        .and_then(|source| TomlText::parse(&source))
+        .map_err(|e| e.context(format!("Error reading {:?}.", manifest_path)))
+}
+
+/// Edit the manifest source with `edit`, preserving its formatting.
+pub fn edit_manifest_text(
+    manifest_path: &Path,
+    edit: impl FnOnce(&mut TomlText) -> CliResult<()>,
 ) -> CliResult<()> {
-    let table = manifest
-        .get_table(table_path)
-        .map_err(|e| CliError::Manifest(e.to_string()))?;
-
-    let array = table
-        .as_table_mut()
-        .and_then(|tm| tm.entry(table_entry).as_array_mut())
-        .ok_or_else(|| {
-            CliError::Manifest(format!(
-                "Error updating '{}' in runtime manifest.",
-                table_path.first().unwrap()
+    let mut text = read_manifest_text(manifest_path)?;
+    let source = text.to_string();
+    edit(&mut text)?;
+    let edited = text.to_string();
+    if edited != source {
+        // Never write a manifest cargo couldn't read, whatever the formatting of the original
+        edited.parse::<toml::Value>().map_err(|e| {
+            CliError::Toml(format!(
+                "Editing {} would make it invalid TOML, leaving it unchanged: {}",
+                manifest_path.display(),
+                e
             ))
         })?;
-
-    if !array.iter().any(|v| v.as_str() == Some(&array_entry)) {
-        array.push(format!("'{}'", array_entry));
+        fs::write(manifest_path, edited)?;
     }
 
     Ok(())
@@ -152,18 +682,35 @@ pub fn pallet_alias<'a>(dependency: &'a Dependency, alias: &Option<&'a str>) ->
     }
 }
 
-fn pallet_dependency_to_toml(
-    name: &str,
-    version: &str,
+fn workspace_dependency_fields(default_features: bool) -> Vec<(&'static str, Value)> {
+    let mut fields = vec![("workspace", Value::Boolean(true))];
+    if !default_features {
+        fields.push(("default-features", Value::Boolean(false)));
+    }
+    fields
+}
+
+/// Fields of the dependency on the crate `package`, from the `registry` or the local crate
+/// directory `path`, whose absolute path is made relative to the manifest declaring the
+/// dependency by `insert_dependency`. Local crates without a version are path only dependencies.
+fn pallet_dependency_fields<'a>(
+    version: Option<&str>,
     package: &str,
-    registry: Option<&str>,
-) -> (String, toml_edit::Table) {
-    let mut data = toml_edit::Table::new();
-    data["package"] = toml_edit::value(format!("'{}'", package));
-    data["version"] = toml_edit::value(format!("'{}'", version));
-    data["default-features"] = toml_edit::value(false);
+    (registry, path): (Option<&str>, Option<&Path>),
+    default_features: bool,
+) -> Vec<(&'a str, Value)> {
+    let mut fields = vec![("package", Value::String(package.to_owned()))];
+    if let Some(version) = version {
+        fields.push(("version", Value::String(version.to_owned())));
+    }
+    if let Some(path) = path {
+        fields.push(("path", Value::String(path.to_string_lossy().into_owned())));
+    }
+    if !default_features {
+        fields.push(("default-features", Value::Boolean(false)));
+    }
     if let Some(registry) = registry {
-        data["registry"] = toml_edit::value(format!("'{}'", registry));
+        fields.push(("registry", Value::String(registry.to_owned())));
     }
-    (name.to_string(), data)
+    fields
 }
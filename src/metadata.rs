@@ -1,5 +1,13 @@
+use crate::error::{CliError, CliResult};
+use crate::graph::FRAME;
+use crate::registry::registry_src_path_from_url;
+
+use cargo_edit::Dependency;
+use log::debug;
 use regex::Regex;
 use serde::Deserialize;
+use std::{collections::BTreeMap, fs, path::Path};
+use url::Url;
 
 lazy_static! {
     static ref PALLET_DEPS_REGEX: Regex = Regex::new(r"([\w\d_-]+):([\w\d_-]+)").unwrap();
@@ -9,18 +17,55 @@ lazy_static! {
 #[derive(Clone, Debug, Deserialize)]
 pub struct Manifest {
     package: Option<Package>,
+    workspace: Option<Workspace>,
+    #[serde(default)]
+    features: BTreeMap<String, Vec<String>>,
 }
 
 impl Manifest {
     pub fn package(&self) -> &Option<Package> {
         &self.package
     }
+
+    /// Names of the features the crate declares, other than `default`.
+    pub fn features(&self) -> Vec<String> {
+        self.features
+            .keys()
+            .filter(|feature| *feature != "default")
+            .cloned()
+            .collect()
+    }
+
+    pub fn workspace(&self) -> &Option<Workspace> {
+        &self.workspace
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Workspace {
+    #[serde(default)]
+    members: Vec<String>,
+    #[serde(default)]
+    exclude: Vec<String>,
+}
+
+impl Workspace {
+    pub fn members(&self) -> &[String] {
+        &self.members
+    }
+
+    /// Whether `dir` is excluded from the workspace rooted at `root`.
+    pub fn excludes(&self, root: &Path, dir: &Path) -> bool {
+        self.exclude.iter().any(|e| dir.starts_with(root.join(e)))
+    }
 }
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct Package {
     name: String,
-    version: String,
+    /// The version of the crate, or a table e.g. `{ workspace = true }` inheriting it.
+    version: Option<toml::Value>,
+    metadata: Option<PackageMetadata>,
 }
 
 impl Package {
@@ -28,8 +73,309 @@ impl Package {
         &self.name
     }
 
-    #[allow(dead_code)]
-    pub fn version(&self) -> &str {
-        &self.version
+    /// The version of the crate, unless it has none or inherits the version of its workspace.
+    pub fn version(&self) -> Option<&str> {
+        self.version.as_ref().and_then(toml::Value::as_str)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct PackageMetadata {
+    substrate: Option<PalletMetadata>,
+}
+
+/// Substrate specific metadata of a pallet, declared in its manifest under
+/// `[package.metadata.substrate]`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PalletMetadata {
+    /// Fields of the pallet genesis config & their default value in the node chain spec,
+    /// if the pallet has a genesis config.
+    genesis_config: Option<BTreeMap<String, String>>,
+    /// Runtime API of the pallet, if it ships a `-rpc-runtime-api` crate.
+    runtime_api: Option<RuntimeApiMetadata>,
+    /// RPC extension of the pallet, if it ships a `-rpc` crate.
+    rpc: Option<RpcMetadata>,
+    /// Parts of the pallet to list in `construct_runtime!` e.g. `Call` or `Event<T>`, when the
+    /// runtime lists them.
+    parts: Option<Vec<String>>,
+}
+
+impl PalletMetadata {
+    pub fn genesis_config(&self) -> &Option<BTreeMap<String, String>> {
+        &self.genesis_config
+    }
+
+    pub fn runtime_api(&self) -> &Option<RuntimeApiMetadata> {
+        &self.runtime_api
+    }
+
+    pub fn rpc(&self) -> &Option<RpcMetadata> {
+        &self.rpc
+    }
+
+    pub fn parts(&self) -> &Option<Vec<String>> {
+        &self.parts
+    }
+}
+
+/// Runtime API of a pallet, implemented by the runtime in `impl_runtime_apis!`.
+///
+/// Templates may refer to the runtime API crate as `{runtime_api}` and to the pallet in the
+/// runtime as `{pallet}`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct RuntimeApiMetadata {
+    /// Name of the runtime API crate, defaults to `<pallet>-rpc-runtime-api`.
+    #[serde(rename = "crate")]
+    crate_name: Option<String>,
+    /// Implementation of the runtime API for `Runtime`.
+    implementation: String,
+}
+
+impl RuntimeApiMetadata {
+    pub fn crate_name(&self, pallet: &str) -> String {
+        self.crate_name
+            .clone()
+            .unwrap_or_else(|| format!("{}-rpc-runtime-api", pallet))
+    }
+
+    pub fn implementation(&self) -> &str {
+        &self.implementation
     }
 }
+
+/// RPC extension of a pallet, registered in `create_full` of the node `rpc.rs`.
+///
+/// Templates may refer to the RPC crate as `{rpc}`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct RpcMetadata {
+    /// Name of the RPC crate, defaults to `<pallet>-rpc`.
+    #[serde(rename = "crate")]
+    crate_name: Option<String>,
+    /// Imports needed by the extension e.g. `use {rpc}::{Contracts, ContractsApi};`.
+    #[serde(default)]
+    imports: Vec<String>,
+    /// Bounds on the client runtime API e.g. `C::Api: {rpc}::ContractsRuntimeApi<Block>`.
+    #[serde(default)]
+    bounds: Vec<String>,
+    /// Statement registering the extension e.g. `io.extend_with(..);`.
+    extension: String,
+}
+
+impl RpcMetadata {
+    pub fn crate_name(&self, pallet: &str) -> String {
+        self.crate_name
+            .clone()
+            .unwrap_or_else(|| format!("{}-rpc", pallet))
+    }
+
+    pub fn imports(&self) -> &[String] {
+        &self.imports
+    }
+
+    pub fn bounds(&self) -> &[String] {
+        &self.bounds
+    }
+
+    pub fn extension(&self) -> &str {
+        &self.extension
+    }
+}
+
+/// Read the metadata of the pallet `dependency`, from its manifest if the crate source was
+/// already downloaded from the registry `reg_url` or is the local crate directory `path`, or from
+/// the templates for FRAME pallets.
+pub fn pallet_metadata(
+    dependency: &Dependency,
+    reg_url: &Url,
+    path: Option<&Path>,
+) -> CliResult<PalletMetadata> {
+    let manifest_path = match path {
+        Some(path) => path.join("Cargo.toml"),
+        None => registry_src_path_from_url(reg_url)?
+            .join(format!(
+                "{}-{}",
+                dependency.name,
+                dependency.version().unwrap_or_default()
+            ))
+            .join("Cargo.toml"),
+    };
+
+    let metadata = if manifest_path.is_file() {
+        debug!("Reading pallet metadata from: {:?}", manifest_path);
+        let s = fs::read_to_string(&manifest_path)?;
+        let manifest: Manifest = toml::from_str(&s)
+            .map_err(|e| CliError::Metadata(format!("Error reading pallet metadata: {}", e)))?;
+        manifest
+            .package
+            .and_then(|package| package.metadata)
+            .and_then(|metadata| metadata.substrate)
+    } else {
+        None
+    };
+
+    Ok(metadata
+        .or_else(|| frame_pallet_metadata(&dependency.name))
+        .unwrap_or_default())
+}
+
+/// Metadata of the FRAME pallets, which don't declare any.
+fn frame_pallet_metadata(name: &str) -> Option<PalletMetadata> {
+    if !FRAME.iter().any(|pallet| pallet == name) {
+        return None;
+    }
+
+    let genesis_config: Option<&[(&str, &str)]> = match name {
+        "pallet-aura" | "pallet-babe" | "pallet-grandpa" => Some(&[("authorities", "vec![]")]),
+        "pallet-authority-discovery" | "pallet-im-online" => Some(&[("keys", "vec![]")]),
+        "pallet-balances" => Some(&[("balances", "vec![]")]),
+        "pallet-collective" | "pallet-membership" => {
+            Some(&[("members", "vec![]"), ("phantom", "Default::default()")])
+        }
+        "pallet-elections-phragmen" => Some(&[("members", "vec![]")]),
+        "pallet-contracts" => Some(&[("current_schedule", "Default::default()")]),
+        "pallet-indices" => Some(&[("indices", "vec![]")]),
+        "pallet-session" => Some(&[("keys", "vec![]")]),
+        "pallet-sudo" => Some(&[("key", "Default::default()")]),
+        "pallet-vesting" => Some(&[("vesting", "vec![]")]),
+        _ => None,
+    };
+
+    let metadata = PalletMetadata {
+        genesis_config: genesis_config.map(|genesis_config| {
+            genesis_config
+                .iter()
+                .map(|(field, value)| (field.to_string(), value.to_string()))
+                .collect()
+        }),
+        ..frame_pallet_rpc_metadata(name)
+    };
+
+    if metadata.genesis_config.is_none() && metadata.runtime_api.is_none() {
+        return None;
+    }
+    Some(metadata)
+}
+
+/// Runtime API & RPC extension of the FRAME pallets which ship them.
+fn frame_pallet_rpc_metadata(name: &str) -> PalletMetadata {
+    let (api_crate, implementation) = match name {
+        "pallet-aura" => (Some("sp-consensus-aura"), AURA_RUNTIME_API),
+        "pallet-authority-discovery" => (
+            Some("sp-authority-discovery"),
+            AUTHORITY_DISCOVERY_RUNTIME_API,
+        ),
+        "pallet-contracts" => (None, CONTRACTS_RUNTIME_API),
+        "pallet-session" => (Some("sp-session"), SESSION_RUNTIME_API),
+        "pallet-transaction-payment" => (None, TRANSACTION_PAYMENT_RUNTIME_API),
+        _ => return PalletMetadata::default(),
+    };
+    let runtime_api = Some(RuntimeApiMetadata {
+        crate_name: api_crate.map(str::to_owned),
+        implementation: implementation.to_owned(),
+    });
+
+    let (imports, bounds, extension) = match name {
+        "pallet-contracts" => (
+            "use {rpc}::{Contracts, ContractsApi};",
+            "C::Api: {rpc}::ContractsRuntimeApi<Block, AccountId, Balance, BlockNumber>,",
+            "io.extend_with(ContractsApi::to_delegate(Contracts::new(client.clone())));",
+        ),
+        "pallet-transaction-payment" => (
+            "use {rpc}::{TransactionPayment, TransactionPaymentApi};",
+            "C::Api: {rpc}::TransactionPaymentRuntimeApi<Block, Balance>,",
+            "io.extend_with(TransactionPaymentApi::to_delegate(TransactionPayment::new(client.clone())));",
+        ),
+        _ => {
+            return PalletMetadata {
+                runtime_api,
+                ..PalletMetadata::default()
+            }
+        }
+    };
+
+    PalletMetadata {
+        runtime_api,
+        rpc: Some(RpcMetadata {
+            crate_name: None,
+            imports: vec![imports.to_owned()],
+            bounds: vec![bounds.to_owned()],
+            extension: extension.to_owned(),
+        }),
+        ..PalletMetadata::default()
+    }
+}
+
+const AURA_RUNTIME_API: &str = "\
+impl {runtime_api}::AuraApi<Block, AuraId> for Runtime {
+	fn slot_duration() -> u64 {
+		{pallet}::slot_duration()
+	}
+
+	fn authorities() -> Vec<AuraId> {
+		{pallet}::authorities()
+	}
+}";
+
+const AUTHORITY_DISCOVERY_RUNTIME_API: &str = "\
+impl {runtime_api}::AuthorityDiscoveryApi<Block> for Runtime {
+	fn authorities() -> Vec<AuthorityDiscoveryId> {
+		{pallet}::authorities()
+	}
+}";
+
+const CONTRACTS_RUNTIME_API: &str = "\
+impl {runtime_api}::ContractsApi<Block, AccountId, Balance, BlockNumber> for Runtime {
+	fn call(
+		origin: AccountId,
+		dest: AccountId,
+		value: Balance,
+		gas_limit: u64,
+		input_data: Vec<u8>,
+	) -> {runtime_api}::ContractExecResult {
+		let (exec_result, gas_consumed) =
+			{pallet}::bare_call(origin, dest.into(), value, gas_limit, input_data);
+		match exec_result {
+			Ok(v) => {runtime_api}::ContractExecResult::Success {
+				flags: v.flags.bits(),
+				data: v.data,
+				gas_consumed,
+			},
+			Err(_) => {runtime_api}::ContractExecResult::Error,
+		}
+	}
+
+	fn get_storage(
+		address: AccountId,
+		key: [u8; 32],
+	) -> pallet_contracts_primitives::GetStorageResult {
+		{pallet}::get_storage(address, key)
+	}
+
+	fn rent_projection(
+		address: AccountId,
+	) -> pallet_contracts_primitives::RentProjectionResult<BlockNumber> {
+		{pallet}::rent_projection(address)
+	}
+}";
+
+const SESSION_RUNTIME_API: &str = "\
+impl {runtime_api}::SessionKeys<Block> for Runtime {
+	fn generate_session_keys(seed: Option<Vec<u8>>) -> Vec<u8> {
+		opaque::SessionKeys::generate(seed)
+	}
+
+	fn decode_session_keys(encoded: Vec<u8>) -> Option<Vec<(Vec<u8>, KeyTypeId)>> {
+		opaque::SessionKeys::decode_into_raw_public_keys(&encoded)
+	}
+}";
+
+const TRANSACTION_PAYMENT_RUNTIME_API: &str = "\
+impl {runtime_api}::TransactionPaymentApi<Block, Balance> for Runtime {
+	fn query_info(
+		uxt: <Block as BlockT>::Extrinsic,
+		len: u32,
+	) -> {runtime_api}::RuntimeDispatchInfo<Balance> {
+		{pallet}::query_info(uxt, len)
+	}
+}";
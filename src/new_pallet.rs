@@ -0,0 +1,380 @@
+use crate::add::add_pallets;
+use crate::error::*;
+use crate::manifest::{dependency_source_fields, find_dependency_key};
+use crate::output::message;
+use crate::runtime::{read_runtime_lib, runtime_config_trait};
+use crate::spec::PalletSpec;
+use crate::toml_text::{inline_table, Value};
+use crate::transaction::Snapshot;
+use crate::workspace::{add_workspace_member, find_workspace_root};
+
+use log::debug;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The FRAME crates a pallet depends on, as `(key, package)` pairs, the `sp-*` crates being used
+/// by the mock runtime of its tests only.
+const PALLET_DEPENDENCIES: [(&str, &str); 3] = [
+    ("codec", "parity-scale-codec"),
+    ("frame-support", "frame-support"),
+    ("frame-system", "frame-system"),
+];
+const PALLET_DEV_DEPENDENCIES: [&str; 3] = ["sp-core", "sp-io", "sp-runtime"];
+
+/// Version of `parity-scale-codec` to use if the runtime doesn't depend on it.
+pub const CODEC_VERSION: &str = "1.3.4";
+
+/// Templates of the pallet crate files, written for the `decl_*` macros & `Trait` of FRAME 2.0,
+/// referring to the crate name as `{name}` & to the pallet as
+/// `{Pallet}` e.g. `Kitties` for `pallet-kitties`.
+const MANIFEST_TEMPLATE: &str = r#"[package]
+name = "{name}"
+version = "0.1.0"
+edition = "2018"
+publish = false
+
+[package.metadata.docs.rs]
+targets = ["x86_64-unknown-linux-gnu"]
+
+[dependencies]
+{dependencies}
+[dev-dependencies]
+{dev-dependencies}
+[features]
+default = ["std"]
+std = [
+{std}]
+"#;
+
+const LIB_TEMPLATE: &str = r#"#![cfg_attr(not(feature = "std"), no_std)]
+
+//! The {Pallet} pallet.
+
+use frame_support::{decl_error, decl_event, decl_module, decl_storage, dispatch, traits::Get};
+use frame_system::ensure_signed;
+
+#[cfg(test)]
+mod mock;
+
+#[cfg(test)]
+mod tests;
+
+/// Configure the pallet by specifying the parameters and types on which it depends.
+pub trait Trait: frame_system::Trait {
+    /// Because this pallet emits events, it depends on the runtime's definition of an event.
+    type Event: From<Event<Self>> + Into<<Self as frame_system::Trait>::Event>;
+}
+
+decl_storage! {
+    trait Store for Module<T: Trait> as {Pallet} {
+        /// An example storage item, holding a single value.
+        Something get(fn something): Option<u32>;
+    }
+}
+
+decl_event!(
+    pub enum Event<T>
+    where
+        AccountId = <T as frame_system::Trait>::AccountId,
+    {
+        /// A value was stored. [something, who]
+        SomethingStored(u32, AccountId),
+    }
+);
+
+decl_error! {
+    pub enum Error for Module<T: Trait> {
+        /// No value was stored.
+        NoneValue,
+        /// The stored value can't be incremented.
+        StorageOverflow,
+    }
+}
+
+decl_module! {
+    pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+        type Error = Error<T>;
+
+        fn deposit_event() = default;
+
+        /// Store a value & emit an event.
+        #[weight = 10_000 + T::DbWeight::get().writes(1)]
+        pub fn do_something(origin, something: u32) -> dispatch::DispatchResult {
+            let who = ensure_signed(origin)?;
+
+            Something::put(something);
+            Self::deposit_event(RawEvent::SomethingStored(something, who));
+            Ok(())
+        }
+
+        /// Increment the stored value, failing if there is none or on overflow.
+        #[weight = 10_000 + T::DbWeight::get().reads_writes(1, 1)]
+        pub fn cause_error(origin) -> dispatch::DispatchResult {
+            let _who = ensure_signed(origin)?;
+
+            let old = Something::get().ok_or(Error::<T>::NoneValue)?;
+            let new = old.checked_add(1).ok_or(Error::<T>::StorageOverflow)?;
+            Something::put(new);
+            Ok(())
+        }
+    }
+}
+"#;
+
+const MOCK_TEMPLATE: &str = r#"use crate::{Module, Trait};
+use frame_support::{impl_outer_origin, parameter_types, weights::Weight};
+use frame_system as system;
+use sp_core::H256;
+use sp_runtime::{
+    testing::Header,
+    traits::{BlakeTwo256, IdentityLookup},
+    Perbill,
+};
+
+impl_outer_origin! {
+    pub enum Origin for Test {}
+}
+
+// Configure a mock runtime to test the pallet.
+#[derive(Clone, Eq, PartialEq)]
+pub struct Test;
+
+parameter_types! {
+    pub const BlockHashCount: u64 = 250;
+    pub const MaximumBlockWeight: Weight = 1024;
+    pub const MaximumBlockLength: u32 = 2 * 1024;
+    pub const AvailableBlockRatio: Perbill = Perbill::from_percent(75);
+}
+
+impl system::Trait for Test {
+    type BaseCallFilter = ();
+    type Origin = Origin;
+    type Call = ();
+    type Index = u64;
+    type BlockNumber = u64;
+    type Hash = H256;
+    type Hashing = BlakeTwo256;
+    type AccountId = u64;
+    type Lookup = IdentityLookup<Self::AccountId>;
+    type Header = Header;
+    type Event = ();
+    type BlockHashCount = BlockHashCount;
+    type MaximumBlockWeight = MaximumBlockWeight;
+    type DbWeight = ();
+    type BlockExecutionWeight = ();
+    type ExtrinsicBaseWeight = ();
+    type MaximumExtrinsicWeight = MaximumBlockWeight;
+    type MaximumBlockLength = MaximumBlockLength;
+    type AvailableBlockRatio = AvailableBlockRatio;
+    type Version = ();
+    type PalletInfo = ();
+    type AccountData = ();
+    type OnNewAccount = ();
+    type OnKilledAccount = ();
+    type SystemWeightInfo = ();
+}
+
+impl Trait for Test {
+    type Event = ();
+}
+
+pub type {Pallet} = Module<Test>;
+
+// Build genesis storage according to the mock runtime.
+pub fn new_test_ext() -> sp_io::TestExternalities {
+    system::GenesisConfig::default()
+        .build_storage::<Test>()
+        .unwrap()
+        .into()
+}
+"#;
+
+const TESTS_TEMPLATE: &str = r#"use crate::{mock::*, Error};
+use frame_support::{assert_noop, assert_ok};
+
+#[test]
+fn it_works_for_default_value() {
+    new_test_ext().execute_with(|| {
+        assert_ok!({Pallet}::do_something(Origin::signed(1), 42));
+        assert_eq!({Pallet}::something(), Some(42));
+    });
+}
+
+#[test]
+fn correct_error_for_none_value() {
+    new_test_ext().execute_with(|| {
+        assert_noop!(
+            {Pallet}::cause_error(Origin::signed(1)),
+            Error::<Test>::NoneValue
+        );
+    });
+}
+"#;
+
+/// Generate the pallet crate `name` in `path`, by default in the `pallets` directory of the
+/// workspace, and add it to the runtime whose manifest is `manifest_path` if `add` is set.
+pub fn execute_new_pallet(
+    manifest_path: &Path,
+    name: &str,
+    path: Option<&Path>,
+    add: bool,
+    registry: Option<&str>,
+) -> CliResult<()> {
+    debug!("Manifest path: {:?}", manifest_path);
+    debug!("Name: {}", name);
+    debug!("Path: {:?}", path);
+    debug!("Add: {}", add);
+    debug!("Registry: {:?}", registry);
+
+    let valid = name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(CliError::Generic(format!(
+            "{} is not a valid crate name.",
+            name
+        )));
+    }
+
+    // The pallet is written for the runtimes implementing the `Trait` of the pallets
+    if runtime_config_trait(&read_runtime_lib(manifest_path)?) != "Trait" {
+        return Err(CliError::Runtime(
+            "Your node runtime implements the Config trait of FRAME v2 pallets, the pallet template is written for the Trait of FRAME 2.0 pallets."
+                .to_owned(),
+        ));
+    }
+
+    let root_path = find_workspace_root(manifest_path)?;
+    let dir = match path {
+        Some(path) => path.to_path_buf(),
+        None => default_pallet_dir(manifest_path, root_path.as_deref(), name)?,
+    };
+    if dir.exists() && fs::read_dir(&dir)?.next().is_some() {
+        return Err(CliError::Generic(format!(
+            "Directory {} already exists and is not empty.",
+            dir.display()
+        )));
+    }
+    debug!("Pallet directory: {:?}", dir);
+
+    // Remove the created files, and restore the workspace & runtime, if the pallet can't be
+    // added to them
+    let mut snapshot = Snapshot::take(manifest_path)?;
+    for file in &["Cargo.toml", "src/lib.rs", "src/mock.rs", "src/tests.rs"] {
+        snapshot.add_missing(&dir.join(file));
+    }
+    (|| {
+        fs::create_dir_all(dir.join("src"))?;
+        write_pallet_crate(manifest_path, name, &dir)?;
+        message(format!("Created pallet {} in {}.", name, dir.display()));
+
+        if let Some(root_path) = &root_path {
+            add_workspace_member(root_path, &dir)?;
+        }
+        if add {
+            let spec = PalletSpec {
+                pallet: name.to_owned(),
+                path: Some(dir.clone()),
+                ..PalletSpec::default()
+            };
+            add_pallets(manifest_path, &[spec], registry)?;
+        }
+        Ok(())
+    })()
+    .map_err(|e| {
+        snapshot.restore();
+        e
+    })
+}
+
+/// Default directory of the pallet crate `name`, in the `pallets` directory of the workspace
+/// e.g. `pallets/kitties` for `pallet-kitties`, or next to the runtime crate without workspace.
+fn default_pallet_dir(
+    manifest_path: &Path,
+    root_path: Option<&Path>,
+    name: &str,
+) -> CliResult<PathBuf> {
+    let dir_name = name
+        .strip_prefix("pallet-")
+        .filter(|dir_name| !dir_name.is_empty())
+        .unwrap_or(name);
+    Ok(match root_path {
+        Some(root_path) => root_path
+            .parent()
+            .unwrap_or_else(|| Path::new("."))
+            .join("pallets")
+            .join(dir_name),
+        None => manifest_path
+            .canonicalize()?
+            .parent()
+            .and_then(Path::parent)
+            .unwrap_or_else(|| Path::new("."))
+            .join(name),
+    })
+}
+
+/// Write the manifest, library, mock runtime & tests of the pallet crate `name` in `dir`, its
+/// FRAME dependencies matching the ones of the runtime whose manifest is `manifest_path`.
+fn write_pallet_crate(manifest_path: &Path, name: &str, dir: &Path) -> CliResult<()> {
+    // The pallet depends on the same version of FRAME as the runtime
+    let frame_fields = |package: &str| -> CliResult<Option<Vec<(&str, Value)>>> {
+        match find_dependency_key(manifest_path, package)? {
+            Some(key) => dependency_source_fields(manifest_path, &key, dir).map(Some),
+            None => Ok(None),
+        }
+    };
+    let frame_support = frame_fields("frame-support")?.ok_or_else(|| {
+        CliError::Dependency(
+            "Your node runtime doesn't depend on frame-support, the FRAME version of the pallet can't be found."
+                .to_owned(),
+        )
+    })?;
+
+    let mut dependencies = String::new();
+    let mut std = String::new();
+    for (key, package) in &PALLET_DEPENDENCIES {
+        let mut fields = match frame_fields(package)? {
+            Some(fields) => fields,
+            None if *package == "parity-scale-codec" => {
+                vec![("version", Value::String(CODEC_VERSION.to_owned()))]
+            }
+            None => frame_support.clone(),
+        };
+        if key != package {
+            fields.insert(0, ("package", Value::String((*package).to_owned())));
+        }
+        fields.push(("default-features", Value::Boolean(false)));
+        if *package == "parity-scale-codec" {
+            fields.push(("features", Value::Array(vec!["derive".to_owned()])));
+        }
+        dependencies.push_str(&format!("{} = {}\n", key, inline_table(&fields)));
+        std.push_str(&format!("    \"{}/std\",\n", key));
+    }
+
+    let mut dev_dependencies = String::new();
+    for package in &PALLET_DEV_DEPENDENCIES {
+        let fields = frame_fields(package)?.unwrap_or_else(|| frame_support.clone());
+        dev_dependencies.push_str(&format!("{} = {}\n", package, inline_table(&fields)));
+    }
+
+    let pallet =
+        inflector::cases::pascalcase::to_pascal_case(name.strip_prefix("pallet-").unwrap_or(name));
+    let render = |template: &str| {
+        template
+            .replace("{name}", name)
+            .replace("{Pallet}", &pallet)
+            .replace("{dependencies}", &dependencies)
+            .replace("{dev-dependencies}", &dev_dependencies)
+            .replace("{std}", &std)
+    };
+
+    fs::write(dir.join("Cargo.toml"), render(MANIFEST_TEMPLATE))?;
+    fs::write(dir.join("src").join("lib.rs"), render(LIB_TEMPLATE))?;
+    fs::write(dir.join("src").join("mock.rs"), render(MOCK_TEMPLATE))?;
+    fs::write(dir.join("src").join("tests.rs"), render(TESTS_TEMPLATE))?;
+
+    Ok(())
+}
@@ -0,0 +1,76 @@
+//! Status lines & messages of the commands, printed to stderr like cargo's e.g.
+//! `    Updating 'https://github.com/rust-lang/crates.io-index' index`, at the verbosity chosen on
+//! the command line.
+
+use std::{
+    fmt::Display,
+    io::Write,
+    sync::atomic::{AtomicBool, AtomicU8, Ordering},
+};
+use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
+
+/// How much the commands report of what they do.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Verbosity {
+    /// Nothing but errors.
+    Quiet,
+    /// Status lines & messages.
+    Normal,
+    /// Status lines, messages & the details of each step.
+    Verbose,
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Quiet as u8);
+static MESSAGES: AtomicBool = AtomicBool::new(true);
+
+/// Set the verbosity of the commands, quiet until set so that library users only get the output
+/// they ask for.
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+pub fn verbosity() -> Verbosity {
+    match VERBOSITY.load(Ordering::Relaxed) {
+        0 => Verbosity::Quiet,
+        1 => Verbosity::Normal,
+        _ => Verbosity::Verbose,
+    }
+}
+
+/// Print the messages for humans or not, e.g. not when the results are printed as JSON, which
+/// report the same.
+pub fn set_messages(enabled: bool) {
+    MESSAGES.store(enabled, Ordering::Relaxed);
+}
+
+/// Print a message to stderr unless quiet or messages are disabled, e.g.
+/// `Added pallet pallet-nicks v2.0.0 configuration in your node runtime.`
+pub fn message(message: impl Display) {
+    if verbosity() == Verbosity::Quiet || !MESSAGES.load(Ordering::Relaxed) {
+        return;
+    }
+
+    // Failing to print a message doesn't fail the command
+    let _ = writeln!(std::io::stderr(), "{}", message);
+}
+
+/// Print a status line to stderr unless quiet, e.g. `      Adding pallet-nicks v2.0.0`, with the
+/// `status` right aligned, and in bold green when stderr is a terminal.
+pub fn status(status: &str, message: impl Display) {
+    if verbosity() == Verbosity::Quiet {
+        return;
+    }
+
+    let color = if atty::is(atty::Stream::Stderr) {
+        ColorChoice::Auto
+    } else {
+        ColorChoice::Never
+    };
+    let mut stderr = StandardStream::stderr(color);
+    // Failing to print a status line doesn't fail the command
+    let _ = stderr
+        .set_color(ColorSpec::new().set_fg(Some(Color::Green)).set_bold(true))
+        .and_then(|_| write!(stderr, "{:>12}", status))
+        .and_then(|_| stderr.reset())
+        .and_then(|_| writeln!(stderr, " {}", message));
+}
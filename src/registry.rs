@@ -1,11 +1,39 @@
 use self::code_from_cargo::Kind;
 use crate::error::*;
+use crate::output::status;
 
-use std::path::{Path, PathBuf};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
 
-use cargo_edit::registry_url;
+use cargo_edit::{registry_url, Dependency};
+use semver::{Version, VersionReq};
+use serde::Deserialize;
 use url::Url;
 
+/// A crate version, as summarized in the registry index.
+#[derive(Deserialize)]
+struct CrateVersion {
+    vers: String,
+    #[serde(default)]
+    features: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    features2: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    deps: Vec<CrateDependency>,
+    #[serde(default)]
+    yanked: bool,
+}
+
+/// A dependency of a crate version, as summarized in the registry index.
+#[derive(Deserialize)]
+struct CrateDependency {
+    name: String,
+    package: Option<String>,
+    kind: Option<String>,
+}
+
 // From https://github.com/tofay/cargo-edit/blob/alt-registries/src/registry.rs
 
 fn cargo_home() -> CliResult<PathBuf> {
@@ -32,6 +60,167 @@ pub fn registry_path_from_url(registry: &Url) -> CliResult<PathBuf> {
         .join(short_name(registry)))
 }
 
+/// Path where cargo extracts the sources of the crates downloaded from the registry.
+pub fn registry_src_path_from_url(registry: &Url) -> CliResult<PathBuf> {
+    Ok(cargo_home()?
+        .join("registry")
+        .join("src")
+        .join(short_name(registry)))
+}
+
+/// Update the local copy of the index of the registry `registry`, reporting it on a status line
+/// rather than on stdout as `cargo_edit::update_registry_index` does.
+pub fn update_registry_index(registry: &Url) -> CliResult<()> {
+    let registry_path = registry_path_from_url(registry)?;
+
+    if !registry_path.exists() {
+        status("Initializing", format!("'{}' index", registry));
+        let mut opts = git2::RepositoryInitOptions::new();
+        opts.bare(true);
+        git2::Repository::init_opts(&registry_path, &opts)?;
+        return Ok(());
+    }
+
+    let repo = git2::Repository::open(&registry_path)?;
+    status("Updating", format!("'{}' index", registry));
+    // Handle force pushes, like cargo does
+    // ref: https://github.com/rust-lang/cargo/blob/57986eac7157261c33f0123bade7ccd20f15200f/src/cargo/sources/git/utils.rs#L758
+    let refspec = "+refs/heads/master:refs/remotes/origin/master";
+    let mut opts = git2::FetchOptions::new();
+    opts.download_tags(git2::AutotagOption::All);
+    let mut remote = repo.remote_anonymous(registry.as_str())?;
+    remote.fetch(&[refspec], Some(&mut opts), None)?;
+
+    Ok(())
+}
+
+/// List the features exposed by the version of `dependency` in the index of the registry
+/// `registry`, excluding `default`, if the version is found in the local copy of the index.
+pub fn crate_features(registry: &Url, dependency: &Dependency) -> CliResult<Option<Vec<String>>> {
+    Ok(crate_version(registry, dependency)?.map(|crate_version| {
+        crate_version
+            .features
+            .into_iter()
+            .chain(crate_version.features2)
+            .map(|(feature, _)| feature)
+            .filter(|feature| feature != "default")
+            .collect()
+    }))
+}
+
+/// List the crates the version of `dependency` in the index of the registry `registry` depends
+/// on at runtime i.e. excluding dev & build dependencies, if the version is found in the local
+/// copy of the index.
+pub fn crate_dependencies(
+    registry: &Url,
+    dependency: &Dependency,
+) -> CliResult<Option<Vec<String>>> {
+    Ok(crate_version(registry, dependency)?.map(|crate_version| {
+        crate_version
+            .deps
+            .into_iter()
+            .filter(|dep| dep.kind.as_deref().unwrap_or("normal") == "normal")
+            .map(|dep| dep.package.unwrap_or(dep.name))
+            .collect()
+    }))
+}
+
+/// List the versions of the crate `name` published in the index of the registry `registry`, if
+/// the crate is found in the local copy of the index.
+pub fn crate_versions(registry: &Url, name: &str) -> CliResult<Option<Vec<String>>> {
+    Ok(crate_summary(registry, name)?
+        .map(|versions| versions.into_iter().map(|version| version.vers).collect()))
+}
+
+/// Find the highest version of the crate `name` compatible with `version` (as `^version`) in the
+/// local copy of the index of the registry `registry`, e.g. the version of the RPC crate of a
+/// pallet matching the pallet version, or its highest stable version without `version`.
+pub fn compatible_version(
+    registry: &Url,
+    name: &str,
+    version: Option<&str>,
+) -> CliResult<Option<String>> {
+    let requirement = match version {
+        Some(version) => Some(
+            VersionReq::parse(&format!("^{}", version))
+                .map_err(|e| CliError::Dependency(format!("{}: {}", version, e)))?,
+        ),
+        None => None,
+    };
+    let versions = crate_summary(registry, name)?
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|crate_version| !crate_version.yanked)
+        .filter_map(|crate_version| Version::parse(&crate_version.vers).ok())
+        .filter(|version| match &requirement {
+            Some(requirement) => requirement.matches(version),
+            None => !version.is_prerelease(),
+        });
+
+    Ok(versions.max().map(|version| version.to_string()))
+}
+
+/// Read the summary of the version of `dependency` from the local copy of the index of the
+/// registry `registry`.
+fn crate_version(registry: &Url, dependency: &Dependency) -> CliResult<Option<CrateVersion>> {
+    let version = match dependency.version() {
+        Some(version) => version,
+        None => return Ok(None),
+    };
+
+    Ok(
+        crate_summary(registry, &dependency.name)?.and_then(|versions| {
+            versions
+                .into_iter()
+                .find(|crate_version| crate_version.vers == version)
+        }),
+    )
+}
+
+/// Read the summaries of the versions of the crate `name` from the local copy of the index of
+/// the registry `registry`.
+fn crate_summary(registry: &Url, name: &str) -> CliResult<Option<Vec<CrateVersion>>> {
+    let registry_path = registry_path_from_url(registry)?;
+    if !registry_path.exists() {
+        return Ok(None);
+    }
+
+    let repo = git2::Repository::open(&registry_path)?;
+    let tree = match repo.find_reference("refs/remotes/origin/master") {
+        Ok(reference) => reference.peel_to_tree()?,
+        Err(_) => return Ok(None),
+    };
+    let entry = match tree.get_path(Path::new(&summary_path(&name.to_lowercase()))) {
+        Ok(entry) => entry,
+        Err(_) => return Ok(None),
+    };
+    let blob = entry.to_object(&repo)?.peel_to_blob()?;
+    let summary = String::from_utf8(blob.content().to_vec())?;
+
+    summary
+        .lines()
+        .map(|line| {
+            serde_json::from_str(line).map_err(|e| {
+                CliError::Registry(format!(
+                    "Error reading {} summary in registry index: {}",
+                    name, e
+                ))
+            })
+        })
+        .collect::<CliResult<_>>()
+        .map(Some)
+}
+
+/// Path of the summary of the crate `name` in the registry index.
+fn summary_path(name: &str) -> String {
+    match name.len() {
+        1 => format!("1/{}", name),
+        2 => format!("2/{}", name),
+        3 => format!("3/{}/{}", &name[..1], name),
+        _ => format!("{}/{}/{}", &name[..2], &name[2..4], name),
+    }
+}
+
 fn short_name(registry: &Url) -> String {
     // ref: https://github.com/rust-lang/cargo/blob/4c1fa54d10f58d69ac9ff55be68e1b1c25ecb816/src/cargo/sources/registry/mod.rs#L386-L390
     #![allow(deprecated)]
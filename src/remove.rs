@@ -0,0 +1,133 @@
+use crate::error::*;
+use crate::genesis::remove_pallet_from_chain_spec;
+use crate::manifest::{
+    find_dependency_key, find_dependency_path, find_dependency_source, find_dependency_version,
+    remove_pallet_from_manifest,
+};
+use crate::metadata::pallet_metadata;
+use crate::output::message;
+use crate::rpc::remove_pallet_runtime_api;
+use crate::runtime::{
+    pallet_names, read_runtime_lib, remove_pallet_benchmark, remove_pallet_from_runtime,
+    runtime_pallet_name, runtime_pallets,
+};
+use crate::transaction::transaction;
+
+use cargo_edit::{registry_url, Dependency};
+use log::debug;
+use serde::Serialize;
+use std::path::Path;
+
+/// A pallet removed from the runtime.
+#[derive(Clone, Debug, Serialize)]
+pub struct RemovedPallet {
+    /// Name of the pallet in the runtime.
+    pub name: String,
+    /// Name of the pallet crate.
+    pub package: String,
+    /// Whether the pallet dependency was removed too, no other instance of the pallet remaining.
+    pub dependency_removed: bool,
+}
+
+/// Remove the `pallet`, or its instance or alias, from the runtime whose manifest is
+/// `manifest_path`.
+pub fn execute_remove(
+    manifest_path: &Path,
+    pallet: &str,
+    alias: Option<&str>,
+    instance: Option<u32>,
+) -> CliResult<RemovedPallet> {
+    debug!("Manifest path: {:?}", manifest_path);
+    debug!("Pallet: {}", pallet);
+    debug!("Alias: {:?}", alias);
+    debug!("Instance: {:?}", instance);
+
+    // Without alias nor instance, the pallet is found by the path of its crate in the runtime
+    let pallet_name = match (alias, instance) {
+        (None, None) => runtime_pallet_name(manifest_path, pallet)?,
+        _ => pallet_names(manifest_path, &Dependency::new(pallet), &alias, instance)?.1,
+    };
+
+    transaction(manifest_path, || {
+        remove_pallet(manifest_path, pallet, &pallet_name)
+    })
+}
+
+/// Remove the pallet named `pallet_name` in the runtime, an instance of the `pallet` crate, and
+/// the pallet dependency unless other instances of it remain.
+pub fn remove_pallet(
+    manifest_path: &Path,
+    pallet: &str,
+    pallet_name: &str,
+) -> CliResult<RemovedPallet> {
+    let dependency = Dependency::new(pallet);
+
+    // Remove pallet config from runtime's lib.rs
+    let removed = remove_pallet_from_runtime(manifest_path, pallet_name)?;
+
+    message(format!(
+        "Removed pallet {} from your node runtime.",
+        removed.name
+    ));
+
+    // Remove pallet benchmarks from runtime's lib.rs
+    if remove_pallet_benchmark(manifest_path, &removed)? {
+        message(format!(
+            "Removed pallet {} benchmarks from your node runtime.",
+            removed.name
+        ));
+    }
+
+    // Remove pallet genesis config from node's chain_spec.rs
+    if remove_pallet_from_chain_spec(manifest_path, &removed)? {
+        message(format!(
+            "Removed pallet {} genesis config from your node chain spec.",
+            removed.name
+        ));
+    }
+
+    // Remove pallet from runtime manifest, unless other instances of it remain
+    let remaining = runtime_pallets(&read_runtime_lib(manifest_path)?)?;
+    let dependency_removed = !remaining.iter().any(|p| p.path == removed.path);
+    if dependency_removed {
+        let key = find_dependency_key(manifest_path, pallet)?.ok_or_else(|| {
+            CliError::Dependency(format!(
+                "Pallet {} is not a dependency of your node runtime.",
+                pallet
+            ))
+        })?;
+        debug!("Dependency key: {}", key);
+
+        // Remove pallet runtime API implementation from runtime's lib.rs, reading the metadata
+        // of the pallet from its source
+        let source = find_dependency_source(manifest_path, &key)?;
+        let registry = source.as_deref().and_then(|s| s.strip_prefix("registry+"));
+        let reg_url =
+            registry_url(manifest_path, registry).map_err(|e| CliError::Registry(e.to_string()))?;
+        let path = find_dependency_path(manifest_path, &key)?;
+        let dependency = match find_dependency_version(manifest_path, &key)? {
+            Some(version) => dependency.set_version(&version),
+            None => dependency,
+        };
+        let metadata = pallet_metadata(&dependency, &reg_url, path.as_deref())?;
+        if remove_pallet_runtime_api(manifest_path, pallet, &metadata)? {
+            message(format!(
+                "Removed pallet {} runtime API implementation from your node runtime.",
+                pallet
+            ));
+        }
+
+        remove_pallet_from_manifest(manifest_path, &key)?;
+
+        message(format!(
+            "Removed pallet {} dependency from your node runtime manifest.",
+            pallet
+        ));
+    }
+
+    Ok(RemovedPallet {
+        name: removed.name,
+        package: pallet.to_owned(),
+        dependency_removed,
+    })
+}
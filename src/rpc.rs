@@ -0,0 +1,304 @@
+use crate::error::{CliError, CliResult};
+use crate::manifest::{
+    add_dependency_to_manifest, add_pallet_to_manifest, crate_path, dependency_key,
+    find_dependency_key, remove_pallet_from_manifest, PalletDependencyOptions,
+};
+use crate::metadata::{PalletMetadata, RpcMetadata};
+use crate::output::message;
+use crate::registry::{compatible_version, crate_features};
+use crate::runtime::{
+    add_runtime_api, indent_unit, last_line_indent, matching_brace, reindent, remove_runtime_api,
+    runtime_lib_path, RuntimePallet,
+};
+use crate::workspace::{find_node_crate, find_node_file};
+
+use std::{fs, path::Path};
+
+use cargo_edit::Dependency;
+use log::{debug, warn};
+use regex::Regex;
+use url::Url;
+
+lazy_static! {
+    static ref CREATE_FULL_REGEX: Regex = Regex::new(r"fn\s+create_full\s*[<(]").unwrap();
+    static ref API_BOUND_REGEX: Regex = Regex::new(r"(?m)^[ \t]*C::Api\s*:[^\n]*\n").unwrap();
+    static ref CLIENT_BOUND_REGEX: Regex = Regex::new(r"(?m)^[ \t]*C\s*:[^\n]*\n").unwrap();
+    static ref USE_REGEX: Regex = Regex::new(r"(?m)^[ \t]*use\s[^;]*;[^\n]*\n").unwrap();
+}
+
+/// Add the runtime API crate of the pallet `dependency` to the runtime manifest, and implement
+/// the runtime API in the runtime. Returns the runtime API crate name in code, if the pallet
+/// ships a runtime API.
+pub fn add_pallet_runtime_api(
+    manifest_path: &Path,
+    dependency: &Dependency,
+    pallet: &RuntimePallet,
+    metadata: &PalletMetadata,
+    (registry, reg_url): (Option<&str>, &Url),
+) -> CliResult<Option<String>> {
+    let runtime_api = match metadata.runtime_api() {
+        Some(runtime_api) => runtime_api,
+        None => return Ok(None),
+    };
+
+    let api_crate = runtime_api.crate_name(&dependency.name);
+    let key = match find_dependency_key(manifest_path, &api_crate)? {
+        Some(key) => key,
+        None => {
+            let api_dependency = companion_dependency(&api_crate, dependency, reg_url)?;
+            let features = crate_features(reg_url, &api_dependency)?;
+            let options = PalletDependencyOptions {
+                registry,
+                pallet_features: features.as_deref(),
+                ..PalletDependencyOptions::default()
+            };
+            add_pallet_to_manifest(manifest_path, &api_dependency, &options)?;
+            message(format!(
+                "Added runtime API {} v{} as dependency in your node runtime manifest.",
+                api_dependency.name,
+                api_dependency.version().unwrap_or_default()
+            ));
+            dependency_key(&api_dependency.name)
+        }
+    };
+    let api_mod = crate_path(&key);
+
+    let implementation = runtime_api
+        .implementation()
+        .replace("{runtime_api}", &api_mod)
+        .replace("{pallet}", &pallet.name);
+    if add_runtime_api(manifest_path, &implementation)? {
+        message(format!(
+            "Added runtime API {} implementation in your node runtime.",
+            api_crate
+        ));
+    }
+
+    Ok(Some(api_mod))
+}
+
+/// Remove the runtime API implementation of the pallet `package` from the runtime, and the
+/// runtime API crate from the runtime manifest if the runtime doesn't use it anymore.
+pub fn remove_pallet_runtime_api(
+    manifest_path: &Path,
+    package: &str,
+    metadata: &PalletMetadata,
+) -> CliResult<bool> {
+    let api_crate = match metadata.runtime_api() {
+        Some(runtime_api) => runtime_api.crate_name(package),
+        None => return Ok(false),
+    };
+    let key = match find_dependency_key(manifest_path, &api_crate)? {
+        Some(key) => key,
+        None => return Ok(false),
+    };
+    let api_mod = crate_path(&key);
+
+    let removed = remove_runtime_api(manifest_path, &api_mod)?;
+    let runtime = fs::read_to_string(runtime_lib_path(manifest_path))?;
+    if !runtime.contains(&format!("{}::", api_mod)) {
+        remove_pallet_from_manifest(manifest_path, &key)?;
+    }
+
+    Ok(removed)
+}
+
+/// Add the RPC extension crate of the pallet `dependency` to the node manifest, and register the
+/// RPC extension in the node.
+pub fn add_pallet_rpc(
+    manifest_path: &Path,
+    dependency: &Dependency,
+    pallet: &RuntimePallet,
+    metadata: &PalletMetadata,
+    runtime_api: Option<&str>,
+    (registry, reg_url): (Option<&str>, &Url),
+) -> CliResult<()> {
+    let rpc = match metadata.rpc() {
+        Some(rpc) => rpc,
+        // Pallets such as pallet-aura only ship a runtime API
+        None if runtime_api.is_some() => return Ok(()),
+        None => {
+            return Err(CliError::Metadata(format!(
+                "Pallet {} doesn't declare any runtime API or RPC extension.",
+                dependency.name
+            )))
+        }
+    };
+    let node_manifest_path = match find_node_crate(manifest_path)? {
+        Some(node_dir) => node_dir.join("Cargo.toml"),
+        None => {
+            warn!(
+                "Couldn't find the node crate, skipping {} RPC extension.",
+                dependency.name
+            );
+            return Ok(());
+        }
+    };
+    debug!("Node manifest: {:?}", node_manifest_path);
+
+    let rpc_dependency =
+        companion_dependency(&rpc.crate_name(&dependency.name), dependency, reg_url)?;
+    let rpc_key = add_dependency_to_manifest(&node_manifest_path, &rpc_dependency, registry)?;
+    let rpc_mod = crate_path(&rpc_key);
+    message(format!(
+        "Added RPC extension {} v{} as dependency in your node manifest.",
+        rpc_dependency.name,
+        rpc_dependency.version().unwrap_or_default()
+    ));
+
+    let rpc_path = match find_node_file(manifest_path, "rpc.rs")? {
+        Some(rpc_path) => rpc_path,
+        None => {
+            warn!(
+                "Couldn't find the node rpc.rs, skipping {} RPC extension registration.",
+                dependency.name
+            );
+            return Ok(());
+        }
+    };
+    debug!("Node RPC: {:?}", rpc_path);
+
+    let template = |s: &str| {
+        s.replace("{rpc}", &rpc_mod)
+            .replace("{runtime_api}", runtime_api.unwrap_or_default())
+            .replace("{pallet}", &pallet.name)
+    };
+    if register_rpc_extension(&rpc_path, rpc, template)? {
+        message(format!(
+            "Registered RPC extension {} in your node.",
+            rpc_dependency.name
+        ));
+    }
+
+    Ok(())
+}
+
+/// Register the RPC extension in `create_full` of the node `rpc.rs`, unless it is already
+/// registered. Returns whether the file was updated.
+fn register_rpc_extension(
+    rpc_path: &Path,
+    rpc: &RpcMetadata,
+    template: impl Fn(&str) -> String,
+) -> CliResult<bool> {
+    let mut buffer = fs::read_to_string(rpc_path)?;
+    let unit = indent_unit(&buffer);
+
+    let extension = template(rpc.extension());
+    if buffer.contains(&extension) {
+        debug!("RPC extension already registered: {}", extension);
+        return Ok(false);
+    }
+
+    let function = CREATE_FULL_REGEX
+        .find(&buffer)
+        .ok_or_else(|| CliError::Generic(format!("couldn't find create_full in {:?}", rpc_path)))?
+        .start();
+    let (open, close) = function_body(&buffer, function).ok_or_else(|| {
+        CliError::Generic(format!("couldn't parse create_full in {:?}", rpc_path))
+    })?;
+
+    // Register the extension before the returned value
+    let body = &buffer[open + 1..close];
+    let ret = open + 1 + body.trim_end().rfind('\n').map_or(0, |i| i + 1);
+    let indent = last_line_indent(&buffer[..buffer[..close].trim_end().len()]);
+    buffer.insert_str(ret, &format!("{}\n\n", reindent(&extension, &indent, unit)));
+
+    // Import the extension types at the start of the body, after the existing imports
+    let imports = rpc
+        .imports()
+        .iter()
+        .map(|import| template(import))
+        .filter(|import| !buffer[function..].contains(import.as_str()))
+        .collect::<Vec<_>>();
+    if !imports.is_empty() {
+        let body = &buffer[open + 1..close];
+        let start = match USE_REGEX.find_iter(body).last() {
+            Some(mat) => open + 1 + mat.end(),
+            None => open + 1 + body.find('\n').map_or(0, |i| i + 1),
+        };
+        let indent = format!("{}{}", last_line_indent(&buffer[..function]), unit);
+        let mut lines = imports
+            .iter()
+            .map(|import| format!("{}\n", reindent(import, &indent, unit)))
+            .collect::<String>();
+        if USE_REGEX.find(body).is_none() {
+            lines.push('\n');
+        }
+        buffer.insert_str(start, &lines);
+    }
+
+    // Bound the client runtime API in the where clause
+    let bounds = rpc
+        .bounds()
+        .iter()
+        .map(|bound| template(bound))
+        .filter(|bound| !buffer[function..].contains(bound.as_str()))
+        .collect::<Vec<_>>();
+    if !bounds.is_empty() {
+        let header = &buffer[function..open];
+        let mat = API_BOUND_REGEX
+            .find_iter(header)
+            .last()
+            .or_else(|| CLIENT_BOUND_REGEX.find_iter(header).last())
+            .ok_or_else(|| {
+                CliError::Generic(format!(
+                    "couldn't find the client bounds of create_full in {:?}",
+                    rpc_path
+                ))
+            })?;
+        let indent = last_line_indent(mat.as_str().trim_end());
+        let lines = bounds
+            .iter()
+            .map(|bound| format!("{}\n", reindent(bound, &indent, unit)))
+            .collect::<String>();
+        buffer.insert_str(function + mat.end(), &lines);
+    }
+
+    fs::write(rpc_path, buffer)?;
+
+    Ok(true)
+}
+
+/// Find the braces of the body of the function starting at `function`.
+fn function_body(s: &str, function: usize) -> Option<(usize, usize)> {
+    // Skip the parameters, which may contain braces in patterns
+    let params = function + s[function..].find('(')?;
+    let mut depth = 0;
+    let mut params_end = None;
+    for (i, c) in s[params..].char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    params_end = Some(params + i);
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+    let params_end = params_end?;
+    let open = params_end + s[params_end..].find('{')?;
+    Some((open, matching_brace(s, open)?))
+}
+
+/// The crate `name` shipped with the pallet `dependency` e.g. its runtime API or RPC crate, at the
+/// highest version compatible with the version of the pallet, so that both depend on the same
+/// FRAME version.
+fn companion_dependency(
+    name: &str,
+    dependency: &Dependency,
+    reg_url: &Url,
+) -> CliResult<Dependency> {
+    let version = compatible_version(reg_url, name, dependency.version())?.ok_or_else(|| {
+        CliError::Dependency(match dependency.version() {
+            Some(version) => format!(
+                "Could not find a version of {} compatible with {} v{}.",
+                name, dependency.name, version
+            ),
+            None => format!("Could not find crate {}.", name),
+        })
+    })?;
+    Ok(Dependency::new(name).set_version(&version))
+}
@@ -1,82 +1,1129 @@
 use crate::error::{CliError, CliResult};
-use crate::manifest::pallet_alias;
+use crate::manifest::{crate_path, dependency_key, find_dependency_key, pallet_alias};
+use crate::metadata::PalletMetadata;
+use crate::output::status;
 
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::Path;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 
 use cargo_edit::Dependency;
-use inflector;
+use log::debug;
 use regex::Regex;
 
+lazy_static! {
+    /// Start of `construct_runtime!`, up to the brace opening its pallets, in its `( … );` or
+    /// `{ … }` form.
+    static ref CONSTRUCT_RUNTIME_REGEX: Regex =
+        Regex::new(r"construct_runtime!\s*[({]\s*pub\s+(?:enum|struct)\s+Runtime[^{]*\{").unwrap();
+    static ref RUNTIME_MODULE_REGEX: Regex =
+        Regex::new(r"#\[(?:frame_support::)?runtime\]\s*(?:pub\s+)?mod\s+\w+\s*\{").unwrap();
+    static ref CONFIG_TRAIT_REGEX: Regex =
+        Regex::new(r"impl\s+[\w:]+::Config\s+for\s+Runtime").unwrap();
+    static ref PALLET_PARTS_REGEX: Regex = Regex::new(r"\w+\s*:\s*[\w:]+::\{").unwrap();
+    static ref CONSTRUCT_RUNTIME_PALLET_REGEX: Regex = Regex::new(
+        r"(?P<name>\w+)\s*:\s*(?P<path>\w+(?:::\w+)*)(?:::<(?P<instance>[\w:]+)>)?(?:::\{(?P<parts>[^}]*)\})?\s*(?:=\s*(?P<index>\d+))?"
+    )
+    .unwrap();
+    /// Pallet of a `#[frame_support::runtime]` module, after its index & other attributes e.g.
+    /// `pallet_collective<Instance1>` or `pallet_collective::Pallet<Runtime, Instance1>`.
+    static ref RUNTIME_MODULE_PALLET_REGEX: Regex = Regex::new(
+        r"#\[runtime::pallet_index\((?P<index>\d+)\)\](?:\s*#\[[^\]]*\])*\s*pub\s+type\s+(?P<name>\w+)\s*=\s*(?P<path>\w+(?:::\w+)*?)(?P<pallet>::Pallet)?\s*(?:<(?P<args>[^<>;]*)>)?\s*;"
+    )
+    .unwrap();
+    static ref IMPL_RUNTIME_APIS_REGEX: Regex = Regex::new(r"impl_runtime_apis!\s*\{").unwrap();
+    static ref ADD_BENCHMARK_REGEX: Regex =
+        Regex::new(r"(?m)^(?P<indent>[ \t]*)add_benchmark!\((?P<args>[^,]+,[^,]+),[^\n]*\n")
+            .unwrap();
+    static ref DEFINE_BENCHMARKS_REGEX: Regex = Regex::new(r"define_benchmarks!\s*\(").unwrap();
+    static ref DEFINE_BENCHMARK_REGEX: Regex =
+        Regex::new(r"(?m)^(?P<indent>[ \t]*)\[\s*\w+(?:::\w+)*\s*,[^\]\n]*\][ \t]*\n").unwrap();
+    static ref TYPE_BINDING_REGEX: Regex =
+        Regex::new(r"type\s+(?P<name>\w+)\s*=\s*(?P<value>[^;]+);").unwrap();
+    static ref COMMENT_REGEX: Regex = Regex::new(r"//[^\n]*|/\*[\s\S]*?\*/").unwrap();
+}
+
+/// A pallet declared in the runtime.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RuntimePallet {
+    /// Name of the pallet in the runtime e.g. `Balances`.
+    pub name: String,
+    /// Path of the pallet module e.g. `pallet_balances`.
+    pub path: String,
+    /// Instance of the pallet e.g. `Instance1`, for instantiable pallets.
+    pub instance: Option<String>,
+    /// Index of the pallet, explicit or implied by its position.
+    pub index: u32,
+    /// Whether the index is explicitly declared.
+    pub explicit_index: bool,
+    /// Parts of the pallet listed in `construct_runtime!` e.g. `Call` or `Event<T>`, if any.
+    pub parts: Vec<String>,
+}
+
+/// The syntax used by the runtime to declare its pallets.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum RuntimeSyntax {
+    /// `construct_runtime!` listing the pallet parts e.g. `Balances: balances::{Module, Call}`.
+    ConstructRuntimeParts,
+    /// `construct_runtime!` inferring the pallet parts e.g. `Balances: pallet_balances`.
+    ConstructRuntime,
+    /// `#[frame_support::runtime]` module declaring a type alias per pallet.
+    RuntimeModule,
+}
+
+/// How the pallets of a runtime are declared & configured, detected from its `lib.rs`.
+#[derive(Clone, Debug)]
+struct RuntimeLayout {
+    syntax: RuntimeSyntax,
+    /// `Config` for FRAME v2 pallets, `Trait` for older ones.
+    config_trait: &'static str,
+    /// Pallets declared in the runtime.
+    pallets: Vec<RuntimePallet>,
+}
+
+/// The pallet trait implemented by the `runtime`: `Config` for FRAME v2 pallets, `Trait` for
+/// older ones.
+pub fn runtime_config_trait(runtime: &str) -> &'static str {
+    if CONFIG_TRAIT_REGEX.is_match(runtime) {
+        "Config"
+    } else {
+        "Trait"
+    }
+}
+
+impl RuntimeLayout {
+    fn detect(runtime: &str) -> CliResult<Self> {
+        let config_trait = runtime_config_trait(runtime);
+
+        let syntax = if RUNTIME_MODULE_REGEX.is_match(runtime) {
+            RuntimeSyntax::RuntimeModule
+        } else if PALLET_PARTS_REGEX.is_match(&runtime[construct_runtime_pallets(runtime)?]) {
+            RuntimeSyntax::ConstructRuntimeParts
+        } else {
+            RuntimeSyntax::ConstructRuntime
+        };
+
+        let layout = Self {
+            syntax,
+            config_trait,
+            pallets: runtime_pallets(runtime)?,
+        };
+
+        debug!("Runtime layout: {:?}", layout);
+        Ok(layout)
+    }
+
+    /// Index following the highest pallet index in the runtime.
+    fn next_index(&self) -> u32 {
+        self.pallets
+            .iter()
+            .map(|pallet| pallet.index + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Parts to list in `construct_runtime!` for a pallet with `metadata`: the parts its metadata
+    /// declares, or `Call`, `Storage`, `Event<T>` & `Config<T>` if it has a genesis config, after
+    /// `Module` or `Pallet` like the other pallets of the runtime.
+    fn pallet_parts(&self, metadata: &PalletMetadata) -> Vec<String> {
+        if let Some(parts) = metadata.parts() {
+            return parts.clone();
+        }
+
+        let lists = |part: &str| {
+            self.pallets
+                .iter()
+                .any(|p| p.parts.iter().any(|x| x == part))
+        };
+        let module = if lists("Pallet") || !lists("Module") && self.config_trait == "Config" {
+            "Pallet"
+        } else {
+            "Module"
+        };
+        let mut parts = vec![module, "Call", "Storage", "Event<T>"];
+        if metadata.genesis_config().is_some() {
+            parts.push("Config<T>");
+        }
+        parts.into_iter().map(str::to_owned).collect()
+    }
+
+    /// Generate the declaration of the pallet `mod_name` named `name` in the runtime.
+    fn pallet_declaration(
+        &self,
+        parts: &[String],
+        name: &str,
+        mod_name: &str,
+        instance: Option<u32>,
+        index: u32,
+        indent: &str,
+    ) -> String {
+        let instance = |separator| {
+            instance
+                .map(|instance| format!("{}<Instance{}>", separator, instance))
+                .unwrap_or_default()
+        };
+        match self.syntax {
+            RuntimeSyntax::ConstructRuntimeParts => format!(
+                "{}{}: {}{}::{{{}}} = {},",
+                indent,
+                name,
+                mod_name,
+                instance("::"),
+                parts.join(", "),
+                index
+            ),
+            RuntimeSyntax::ConstructRuntime => format!(
+                "{}{}: {}{} = {},",
+                indent,
+                name,
+                mod_name,
+                instance("::"),
+                index
+            ),
+            RuntimeSyntax::RuntimeModule => format!(
+                "{}#[runtime::pallet_index({})]\n{}pub type {} = {}{};",
+                indent,
+                index,
+                indent,
+                name,
+                mod_name,
+                instance("")
+            ),
+        }
+    }
+}
+
 pub fn add_pallet_to_runtime(
     manifest_path: &Path,
     dependency: &Dependency,
     alias: &Option<&str>,
-) -> CliResult<()> {
-    let runtime_lib_path = manifest_path.parent().unwrap().join("src").join("lib.rs");
-    let mod_name = &inflector::cases::camelcase::to_camel_case(pallet_alias(dependency, alias));
+    instance: Option<u32>,
+    index: Option<u32>,
+    metadata: &PalletMetadata,
+) -> CliResult<RuntimePallet> {
+    let runtime_lib_path = runtime_lib_path(manifest_path);
+    let (mod_name, pallet_name) = &pallet_names(manifest_path, dependency, alias, instance)?;
+
+    let original = read_runtime_lib(manifest_path)?;
+    let layout = RuntimeLayout::detect(&original)?;
+
+    if layout.pallets.iter().any(|p| &p.name == pallet_name) {
+        return Err(CliError::Runtime(format!(
+            "Pallet {} is already declared in the runtime",
+            pallet_name
+        )));
+    }
+
+    // Assign the pallet an explicit index, so that reordering pallets doesn't change it
+    let index = index.unwrap_or_else(|| layout.next_index());
+    if let Some(pallet) = layout.pallets.iter().find(|p| p.index == index) {
+        return Err(CliError::Runtime(format!(
+            "Pallet index {} is already used by pallet {}",
+            index, pallet.name
+        )));
+    }
+    debug!("Pallet index: {}", index);
+
+    let pallet_trait = pallet_trait(&layout, mod_name, instance);
 
     let pallet_trait_existing = Regex::new(
         format!(
             r"(?xm)
-                ^impl\s+{}::Trait\s+for\s+Runtime\s+\{{
-                    [^\}}]+
+                ^impl\s+{}\s+for\s+Runtime\s+\{{
+                    [^\}}]*
                 \}}
         ",
-            mod_name
+            regex::escape(&pallet_trait)
         )
         .as_ref(),
     )?;
 
-    let construct_runtime = Regex::new(
-        r"construct_runtime!\(\s+pub\s+enum\s+Runtime[^{]+\{(?P<pallets>[\s\S]+)\}\s+\);",
-    )?;
-
-    let mut pallet_trait_impl = format!("impl {}::Trait for Runtime {{ \n", mod_name);
+    let mut pallet_trait_impl = format!("impl {} for Runtime {{ \n", pallet_trait);
     pallet_trait_impl.push_str(&format!(
-        "	/* {} Trait config goes here */ \n",
-        dependency.name
+        "	/* {} {} implementation goes here */ \n",
+        dependency.name, layout.config_trait
     ));
-    pallet_trait_impl.push_str("}");
-
-    let mut pallet_config = format!(
-        r"
-        {}: {}::{{",
-        inflector::cases::pascalcase::to_pascal_case(&mod_name),
-        mod_name
-    );
-    pallet_config.push_str(&format!(
-        "	/* {} runtime config goes here */ \n",
-        dependency.name
-    ));
-    pallet_config.push_str("},");
+    pallet_trait_impl.push('}');
 
-    let original = fs::read_to_string(&runtime_lib_path)?;
     let mut buffer = original.clone();
     buffer = if pallet_trait_existing.is_match(&original) {
         let result =
             pallet_trait_existing.replace(&original, |_caps: &regex::Captures| &pallet_trait_impl);
         result.into()
     } else {
-        let mat = construct_runtime
-            .find(&original)
-            .ok_or_else(|| CliError::Generic("couldn't find construct_runtime call".to_owned()))?;
-        buffer.insert_str(mat.start(), format!("{}\n\n", pallet_trait_impl).as_str());
+        let start = match layout.syntax {
+            RuntimeSyntax::RuntimeModule => RUNTIME_MODULE_REGEX.find(&original),
+            _ => CONSTRUCT_RUNTIME_REGEX.find(&original),
+        }
+        .ok_or_else(|| CliError::Runtime("couldn't find construct_runtime call".to_owned()))?
+        .start();
+        buffer.insert_str(start, format!("{}\n\n", pallet_trait_impl).as_str());
         buffer
     };
 
-    let modified = buffer.clone();
-    let caps = construct_runtime
-        .captures(&modified)
-        .ok_or_else(|| CliError::Generic("couldn't find construct_runtime call".to_owned()))?;
-    let pallets = caps.name("pallets").ok_or_else(|| {
-        CliError::Generic(
+    let pallets_end = match layout.syntax {
+        RuntimeSyntax::RuntimeModule => {
+            let mat = RUNTIME_MODULE_REGEX
+                .find(&buffer)
+                .ok_or_else(|| CliError::Runtime("couldn't find runtime module".to_owned()))?;
+            matching_brace(&buffer, mat.end() - 1).ok_or_else(|| {
+                CliError::Runtime("couldn't find the end of the runtime module".to_owned())
+            })?
+        }
+        _ => construct_runtime_pallets(&buffer)?.end,
+    };
+
+    // Insert the pallet declaration after the last one, with the same indentation
+    let last = buffer[..pallets_end].trim_end().len();
+    let indent = last_line_indent(&buffer[..last]);
+    let mut pallet_config = String::new();
+    if layout.syntax != RuntimeSyntax::RuntimeModule && !buffer[..last].ends_with(',') {
+        pallet_config.push(',');
+    }
+    pallet_config.push('\n');
+    if layout.syntax == RuntimeSyntax::RuntimeModule {
+        pallet_config.push('\n');
+    }
+    pallet_config.push_str(&layout.pallet_declaration(
+        &layout.pallet_parts(metadata),
+        pallet_name,
+        mod_name,
+        instance,
+        index,
+        &indent,
+    ));
+
+    buffer.insert_str(last, &pallet_config);
+    fs::write(runtime_lib_path, buffer)?;
+
+    Ok(RuntimePallet {
+        name: pallet_name.to_owned(),
+        path: mod_name.to_owned(),
+        instance: instance.map(|instance| format!("Instance{}", instance)),
+        index,
+        explicit_index: true,
+        parts: Vec::new(),
+    })
+}
+
+/// Remove the pallet named `pallet_name` from the runtime, returning its former declaration.
+pub fn remove_pallet_from_runtime(
+    manifest_path: &Path,
+    pallet_name: &str,
+) -> CliResult<RuntimePallet> {
+    let runtime_lib_path = runtime_lib_path(manifest_path);
+
+    let original = read_runtime_lib(manifest_path)?;
+    let layout = RuntimeLayout::detect(&original)?;
+    let pallet = layout
+        .pallets
+        .iter()
+        .find(|p| p.name == pallet_name)
+        .cloned()
+        .ok_or_else(|| {
+            CliError::Runtime(format!(
+                "Pallet {} is not declared in the runtime",
+                pallet_name
+            ))
+        })?;
+
+    let mut buffer = original;
+
+    // Remove the pallet trait implementation, and the blank line following it
+    if let Some((start, _, close)) = pallet_impl_block(&buffer, &pallet)? {
+        let end = close + 1 + trailing_blank_lines(&buffer[close + 1..]);
+        buffer.replace_range(start..end, "");
+    }
+
+    // Remove the pallet declaration
+    match layout.syntax {
+        RuntimeSyntax::RuntimeModule => {
+            let declaration = format!(
+                r"(?m)(?:^[ \t]*\n)?^[ \t]*#\[runtime::pallet_index\(\d+\)\](?:\s*#\[[^\]]*\])*\s*pub\s+type\s+{}\s*=[^;]*;[ \t]*\n",
+                pallet_name
+            );
+            buffer = Regex::new(&declaration)?.replace(&buffer, "").into_owned();
+        }
+        _ => {
+            if let Some((start, end)) = construct_runtime_entry(&buffer, pallet_name)? {
+                buffer.replace_range(start..end, "");
+            }
+        }
+    }
+
+    fs::write(runtime_lib_path, buffer)?;
+
+    Ok(pallet)
+}
+
+/// Add the runtime API `implementation` to the `impl_runtime_apis!` of the runtime, unless it
+/// already implements the same API. Returns whether the runtime was updated.
+pub fn add_runtime_api(manifest_path: &Path, implementation: &str) -> CliResult<bool> {
+    let runtime_lib_path = runtime_lib_path(manifest_path);
+    let mut buffer = fs::read_to_string(&runtime_lib_path)?;
+    if !insert_runtime_api(&mut buffer, implementation)? {
+        return Ok(false);
+    }
+
+    fs::write(runtime_lib_path, buffer)?;
+
+    Ok(true)
+}
+
+/// Insert the runtime API `implementation` after the last implementation of the
+/// `impl_runtime_apis!` of the `runtime` code. An existing implementation of the same API is left
+/// as is, reporting it if it differs e.g. because it was written by hand. Returns whether the
+/// implementation was inserted.
+fn insert_runtime_api(runtime: &mut String, implementation: &str) -> CliResult<bool> {
+    let (open, close) = runtime_apis_block(runtime)?;
+
+    // Identify the implemented API by its trait path, ignoring its generic parameters
+    let api = implementation
+        .trim_start()
+        .trim_start_matches("impl")
+        .trim_start()
+        .split(|c: char| c == '<' || c.is_whitespace())
+        .next()
+        .unwrap_or_default();
+    let existing = Regex::new(&format!(r"(?m)^[ \t]*impl\s+{}\b", regex::escape(api)))?;
+
+    let unit = indent_unit(runtime);
+    let indent = format!("{}{}", last_line_indent(&runtime[..open]), unit);
+    let implementation = reindent(implementation, &indent, unit);
+
+    if let Some((start, end)) = runtime_api_impl(runtime, open, close, &existing)? {
+        if runtime[start..end] == implementation {
+            debug!("Runtime API already implemented: {}", api);
+        } else {
+            status(
+                "Skipping",
+                format!(
+                    "runtime API {}, already implemented differently in your node runtime",
+                    api
+                ),
+            );
+        }
+        return Ok(false);
+    }
+
+    // Insert the implementation after the last one, with the same indentation
+    let last = runtime[..close].trim_end().len();
+    runtime.insert_str(last, &format!("\n\n{}", implementation));
+
+    Ok(true)
+}
+
+/// Remove the implementations of the runtime APIs of the crate `api_crate` e.g.
+/// `pallet_contracts_rpc_runtime_api` from the `impl_runtime_apis!` of the runtime. Returns
+/// whether the runtime was updated.
+pub fn remove_runtime_api(manifest_path: &Path, api_crate: &str) -> CliResult<bool> {
+    let runtime_lib_path = runtime_lib_path(manifest_path);
+    let mut buffer = fs::read_to_string(&runtime_lib_path)?;
+    let existing = Regex::new(&format!(
+        r"(?m)^[ \t]*impl\s+{}::",
+        regex::escape(api_crate)
+    ))?;
+
+    let mut updated = false;
+    loop {
+        let (open, close) = runtime_apis_block(&buffer)?;
+        let (start, end) = match runtime_api_impl(&buffer, open, close, &existing)? {
+            Some(range) => range,
+            None => break,
+        };
+        // Remove the blank line preceding the implementation as well
+        let start = buffer[..start].trim_end().len().max(open + 1).min(start);
+        buffer.replace_range(start..end, "");
+        updated = true;
+    }
+
+    if updated {
+        fs::write(runtime_lib_path, buffer)?;
+    }
+
+    Ok(updated)
+}
+
+/// Find the braces of the `impl_runtime_apis!` block of the runtime.
+fn runtime_apis_block(runtime: &str) -> CliResult<(usize, usize)> {
+    let open = IMPL_RUNTIME_APIS_REGEX
+        .find(runtime)
+        .ok_or_else(|| CliError::Runtime("couldn't find impl_runtime_apis call".to_owned()))?
+        .end()
+        - 1;
+    let close = matching_brace(runtime, open).ok_or_else(|| {
+        CliError::Runtime("couldn't find the end of impl_runtime_apis call".to_owned())
+    })?;
+    Ok((open, close))
+}
+
+/// Find the implementation whose header matches `header` within the `impl_runtime_apis!` block,
+/// from the start of its first line to its closing brace.
+fn runtime_api_impl(
+    runtime: &str,
+    open: usize,
+    close: usize,
+    header: &Regex,
+) -> CliResult<Option<(usize, usize)>> {
+    let mat = match header.find(&runtime[open + 1..close]) {
+        Some(mat) => mat,
+        None => return Ok(None),
+    };
+    let start = open + 1 + mat.start();
+    let brace = start
+        + runtime[start..close].find('{').ok_or_else(|| {
+            CliError::Runtime(format!(
+                "couldn't parse runtime API {}",
+                mat.as_str().trim()
+            ))
+        })?;
+    let end = matching_brace(runtime, brace).ok_or_else(|| {
+        CliError::Runtime(format!(
+            "couldn't find the end of runtime API {}",
+            mat.as_str().trim()
+        ))
+    })?;
+    Ok(Some((start, end + 1)))
+}
+
+/// Add the benchmarks of `pallet` next to the other pallets benchmarks in the runtime, either in
+/// `add_benchmark!` calls or in `define_benchmarks!`. Returns whether the runtime was updated.
+pub fn add_pallet_benchmark(manifest_path: &Path, pallet: &RuntimePallet) -> CliResult<bool> {
+    let runtime_lib_path = runtime_lib_path(manifest_path);
+    let mut buffer = fs::read_to_string(&runtime_lib_path)?;
+
+    if let Some(caps) = ADD_BENCHMARK_REGEX.captures_iter(&buffer).last() {
+        let end = caps.get(0).map_or(0, |mat| mat.end());
+        let line = format!(
+            "{}add_benchmark!({}, {}, {});\n",
+            &caps["indent"], &caps["args"], pallet.path, pallet.name
+        );
+        buffer.insert_str(end, &line);
+    } else if let Some(mat) = DEFINE_BENCHMARKS_REGEX.find(&buffer) {
+        let body = &buffer[mat.end()..];
+        let body = &body[..body.find(");").unwrap_or(body.len())];
+        let caps = DEFINE_BENCHMARK_REGEX
+            .captures_iter(body)
+            .last()
+            .ok_or_else(|| {
+                CliError::Runtime("couldn't find benchmarks in define_benchmarks".to_owned())
+            })?;
+        let end = mat.end() + caps.get(0).map_or(0, |mat| mat.end());
+        let line = format!("{}[{}, {}]\n", &caps["indent"], pallet.path, pallet.name);
+        buffer.insert_str(end, &line);
+    } else {
+        debug!("Couldn't find the runtime benchmarks.");
+        return Ok(false);
+    }
+
+    fs::write(runtime_lib_path, buffer)?;
+
+    Ok(true)
+}
+
+/// Remove the benchmarks of `pallet` from the runtime. Returns whether the runtime was updated.
+pub fn remove_pallet_benchmark(manifest_path: &Path, pallet: &RuntimePallet) -> CliResult<bool> {
+    let runtime_lib_path = runtime_lib_path(manifest_path);
+    let buffer = fs::read_to_string(&runtime_lib_path)?;
+
+    let benchmark = Regex::new(&format!(
+        r"(?m)^[ \t]*(?:add_benchmark!\([^,]+,[^,]+,\s*{path}\s*,\s*{name}\s*\);|\[\s*{path}\s*,\s*{name}\s*\])[ \t]*\n",
+        path = regex::escape(&pallet.path),
+        name = regex::escape(&pallet.name)
+    ))?;
+    if !benchmark.is_match(&buffer) {
+        return Ok(false);
+    }
+
+    fs::write(
+        runtime_lib_path,
+        benchmark.replace_all(&buffer, "").as_ref(),
+    )?;
+
+    Ok(true)
+}
+
+/// Derive the path of the pallet crate in code & the name of the pallet in the runtime, e.g.
+/// `pallet_balances` & `PalletBalances` for `pallet-balances`, or `balances` & `Balances` with
+/// the alias `balances`.
+pub fn pallet_names(
+    manifest_path: &Path,
+    dependency: &Dependency,
+    alias: &Option<&str>,
+    instance: Option<u32>,
+) -> CliResult<(String, String)> {
+    Ok(match instance {
+        // Instances share the pallet dependency, the alias only names the instance
+        Some(instance) => {
+            let key = find_dependency_key(manifest_path, &dependency.name)?
+                .unwrap_or_else(|| dependency_key(&dependency.name));
+            let mod_name = crate_path(&key);
+            let pallet_name = match alias {
+                Some(alias) => inflector::cases::pascalcase::to_pascal_case(alias),
+                None => format!(
+                    "{}Instance{}",
+                    inflector::cases::pascalcase::to_pascal_case(&mod_name),
+                    instance
+                ),
+            };
+            (mod_name, pallet_name)
+        }
+        None => {
+            let mod_name = crate_path(&dependency_key(pallet_alias(dependency, alias)));
+            let pallet_name = inflector::cases::pascalcase::to_pascal_case(&mod_name);
+            (mod_name, pallet_name)
+        }
+    })
+}
+
+/// Find the name of the runtime pallet declared with the `pallet` crate & no instance, e.g.
+/// `Sudo` for `Sudo: pallet_sudo`, as runtimes name pallets independently of their crate.
+pub fn runtime_pallet_name(manifest_path: &Path, pallet: &str) -> CliResult<String> {
+    let key = find_dependency_key(manifest_path, pallet)?.unwrap_or_else(|| dependency_key(pallet));
+    let mod_name = crate_path(&key);
+    let names = runtime_pallets(&read_runtime_lib(manifest_path)?)?
+        .into_iter()
+        .filter(|p| p.path == mod_name && p.instance.is_none())
+        .map(|p| p.name)
+        .collect::<Vec<_>>();
+    match names.as_slice() {
+        [name] => Ok(name.clone()),
+        [] => Err(CliError::Runtime(format!(
+            "Pallet {} is not declared in the runtime",
+            pallet
+        ))),
+        _ => Err(CliError::Runtime(format!(
+            "Pallet {} is declared several times in the runtime, as {}: select one with --alias",
+            pallet,
+            names.join(", ")
+        ))),
+    }
+}
+
+/// The pallet trait implemented by the runtime e.g. `balances::Trait`.
+fn pallet_trait(layout: &RuntimeLayout, mod_name: &str, instance: Option<u32>) -> String {
+    match instance {
+        Some(instance) => format!(
+            "{}::{}<{}::Instance{}>",
+            mod_name, layout.config_trait, mod_name, instance
+        ),
+        None => format!("{}::{}", mod_name, layout.config_trait),
+    }
+}
+
+/// Find the implementation of the pallet trait for the runtime of `pallet`, as the start of its
+/// first line & the positions of its braces.
+fn pallet_impl_block(
+    runtime: &str,
+    pallet: &RuntimePallet,
+) -> CliResult<Option<(usize, usize, usize)>> {
+    let header = Regex::new(&format!(
+        r"(?m)^[ \t]*impl\s+{}::(?:Trait|Config)\s*(?:<(?P<instance>[^>{{]*)>)?\s+for\s+Runtime\s*\{{",
+        regex::escape(&pallet.path)
+    ))?;
+    let instance_name = |instance: &str| {
+        instance
+            .rsplit("::")
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_owned()
+    };
+    let instance = pallet.instance.as_deref().map(instance_name);
+
+    for caps in header.captures_iter(runtime) {
+        if caps.name("instance").map(|i| instance_name(i.as_str())) != instance {
+            continue;
+        }
+        let mat = caps.get(0).unwrap(); // the whole match always exists
+        let open = mat.end() - 1;
+        let close = matching_brace(runtime, open).ok_or_else(|| {
+            CliError::Runtime(format!("couldn't find the end of {}", mat.as_str().trim()))
+        })?;
+        return Ok(Some((mat.start(), open, close)));
+    }
+
+    Ok(None)
+}
+
+/// List the associated types bound by the runtime in its implementation of the trait of
+/// `pallet`, e.g. `Currency` to `Balances`, with their whitespace normalized.
+pub fn pallet_types(runtime: &str, pallet: &RuntimePallet) -> CliResult<BTreeMap<String, String>> {
+    let body = match pallet_impl_block(runtime, pallet)? {
+        Some((_, open, close)) => COMMENT_REGEX.replace_all(&runtime[open + 1..close], ""),
+        None => return Ok(BTreeMap::new()),
+    };
+
+    Ok(TYPE_BINDING_REGEX
+        .captures_iter(&body)
+        .map(|caps| (caps["name"].to_owned(), normalize_type(&caps["value"])))
+        .collect())
+}
+
+/// Bind the associated types `types` in the implementation of the trait of `pallet` by the
+/// runtime, replacing the existing bindings. Returns whether the runtime was updated.
+pub fn set_pallet_types(
+    manifest_path: &Path,
+    pallet: &RuntimePallet,
+    types: &BTreeMap<String, String>,
+) -> CliResult<bool> {
+    let runtime_lib_path = runtime_lib_path(manifest_path);
+    let original = fs::read_to_string(&runtime_lib_path)?;
+    let unit = indent_unit(&original);
+
+    let mut buffer = original.clone();
+    for (name, value) in types {
+        let (_, open, close) = pallet_impl_block(&buffer, pallet)?.ok_or_else(|| {
+            CliError::Runtime(format!(
+                "couldn't find the trait implementation of pallet {}",
+                pallet.name
+            ))
+        })?;
+        let binding = Regex::new(&format!(
+            r"type\s+{}\s*=\s*(?P<value>[^;]+);",
+            regex::escape(name)
+        ))?;
+        match binding.captures(&buffer[open + 1..close]) {
+            Some(caps) => {
+                if normalize_type(&caps["value"]) == normalize_type(value) {
+                    continue;
+                }
+                let mat = caps.name("value").unwrap(); // value is not optional
+                buffer.replace_range(open + 1 + mat.start()..open + 1 + mat.end(), value);
+            }
+            // Insert the binding after the last line of the implementation
+            None => {
+                let last = buffer[..close].trim_end().len();
+                let spaces = buffer[last..close].len()
+                    - buffer[last..close].trim_start_matches([' ', '\t']).len();
+                let mut binding = format!("\n{}type {} = {};", unit, name, value);
+                if !buffer[last..close].contains('\n') {
+                    binding.push('\n');
+                }
+                buffer.replace_range(last..last + spaces, &binding);
+            }
+        }
+    }
+
+    if buffer == original {
+        return Ok(false);
+    }
+    fs::write(runtime_lib_path, buffer)?;
+
+    Ok(true)
+}
+
+/// Set the explicit index of the pallet named `pallet_name` in the runtime to `index`. Returns
+/// whether the runtime was updated.
+pub fn set_pallet_index(manifest_path: &Path, pallet_name: &str, index: u32) -> CliResult<bool> {
+    let runtime_lib_path = runtime_lib_path(manifest_path);
+    let mut buffer = read_runtime_lib(manifest_path)?;
+    let layout = RuntimeLayout::detect(&buffer)?;
+
+    let pallet = layout
+        .pallets
+        .iter()
+        .find(|p| p.name == pallet_name)
+        .ok_or_else(|| {
+            CliError::Runtime(format!(
+                "Pallet {} is not declared in the runtime",
+                pallet_name
+            ))
+        })?;
+    if pallet.index == index && pallet.explicit_index {
+        return Ok(false);
+    }
+    if let Some(other) = layout
+        .pallets
+        .iter()
+        .find(|p| p.index == index && p.name != pallet_name)
+    {
+        return Err(CliError::Runtime(format!(
+            "Pallet index {} is already used by pallet {}",
+            index, other.name
+        )));
+    }
+
+    if layout.syntax == RuntimeSyntax::RuntimeModule {
+        let declaration = Regex::new(&format!(
+            r"#\[runtime::pallet_index\(\d+\)\](?P<alias>(?:\s*#\[[^\]]*\])*\s*pub\s+type\s+{}\s*=)",
+            pallet_name
+        ))?;
+        buffer = declaration
+            .replace(
+                &buffer,
+                format!("#[runtime::pallet_index({})]$alias", index).as_str(),
+            )
+            .into_owned();
+    } else {
+        // The declaration ends at the first comma outside of the pallet parts
+        let pallets = construct_runtime_pallets(&buffer)?;
+        let start = Regex::new(&format!(r"(?m)^[ \t]*{}\s*:", pallet_name))?
+            .find(&buffer[pallets.clone()])
+            .map(|mat| pallets.start + mat.start())
+            .ok_or_else(|| {
+                CliError::Runtime(format!("couldn't find the declaration of {}", pallet_name))
+            })?;
+        let mut depth = 0;
+        let end = buffer[start..pallets.end]
+            .char_indices()
+            .find(|&(_, c)| {
+                match c {
+                    '{' | '<' => depth += 1,
+                    '}' | '>' => depth -= 1,
+                    _ => {}
+                }
+                depth == 0 && (c == ',' || c == '\n')
+            })
+            .map_or(pallets.end, |(i, _)| start + i);
+        let end = buffer[..end].trim_end().len();
+        let declaration = &buffer[start..end];
+        let declaration = match declaration.rfind('=') {
+            Some(eq) if declaration[eq + 1..].trim().parse::<u32>().is_ok() => {
+                format!("{}= {}", &declaration[..eq], index)
+            }
+            _ => format!("{} = {}", declaration, index),
+        };
+        buffer.replace_range(start..end, &declaration);
+    }
+
+    fs::write(runtime_lib_path, buffer)?;
+
+    Ok(true)
+}
+
+/// Normalize the whitespace of the type `value`, to compare type bindings.
+pub fn normalize_type(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Read the `lib.rs` of the runtime crate whose manifest is `manifest_path`, which must declare
+/// the runtime pallets.
+pub fn read_runtime_lib(manifest_path: &Path) -> CliResult<String> {
+    let lib_path = runtime_lib_path(manifest_path);
+    let runtime = fs::read_to_string(&lib_path)?;
+    check_runtime_declaration(&lib_path, &runtime)?;
+    Ok(runtime)
+}
+
+/// Check that the runtime library `file` of source `runtime` declares the runtime pallets, with
+/// `construct_runtime!` or a runtime module.
+pub fn check_runtime_declaration(file: &Path, runtime: &str) -> CliResult<()> {
+    if RUNTIME_MODULE_REGEX.is_match(runtime) || construct_runtime_pallets(runtime).is_ok() {
+        Ok(())
+    } else {
+        Err(CliError::ConstructRuntimeNotFound {
+            file: file.to_path_buf(),
+        })
+    }
+}
+
+/// Span of the pallets declared in the `construct_runtime!` of the runtime, between its braces.
+fn construct_runtime_pallets(runtime: &str) -> CliResult<Range<usize>> {
+    let mat = CONSTRUCT_RUNTIME_REGEX
+        .find(runtime)
+        .ok_or_else(|| CliError::Runtime("couldn't find construct_runtime call".to_owned()))?;
+    let close = matching_brace(runtime, mat.end() - 1).ok_or_else(|| {
+        CliError::Runtime(
             "couldn't find runtime pallets config inside construct_runtime".to_owned(),
         )
     })?;
+    Ok(mat.end()..close)
+}
 
-    buffer.insert_str(pallets.end() - 2, &pallet_config);
-    fs::write(runtime_lib_path, buffer)?;
+/// Find the declaration of the pallet `pallet_name` in the `construct_runtime!` of the runtime,
+/// from the start of its line to the end of the line of its closing `,`, as the declaration
+/// may span several lines.
+fn construct_runtime_entry(runtime: &str, pallet_name: &str) -> CliResult<Option<(usize, usize)>> {
+    let pallets = construct_runtime_pallets(runtime)?;
+    let entry = Regex::new(&format!(r"(?m)^[ \t]*{}\s*:", regex::escape(pallet_name)))?;
+    let mat = match entry.find(&runtime[pallets.clone()]) {
+        Some(mat) => mat,
+        None => return Ok(None),
+    };
+
+    // The entry ends at the first comma outside of its parts and generics, or with the list
+    let mut depth = 0;
+    let mut end = pallets.end;
+    for (i, c) in runtime[pallets.start + mat.end()..pallets.end].char_indices() {
+        match c {
+            '{' | '(' | '[' | '<' => depth += 1,
+            '}' | ')' | ']' | '>' => depth -= 1,
+            ',' if depth == 0 => {
+                end = pallets.start + mat.end() + i + 1;
+                break;
+            }
+            _ => {}
+        }
+    }
+    let rest = &runtime[end..pallets.end];
+    let line_end = rest.find('\n').map_or(rest.len(), |i| i + 1);
+    if rest[..line_end].trim().is_empty() || rest[..line_end].trim_start().starts_with("//") {
+        end += line_end;
+    }
+
+    Ok(Some((pallets.start + mat.start(), end)))
+}
+
+/// List the pallets declared in the runtime, in declaration order.
+pub fn runtime_pallets(runtime: &str) -> CliResult<Vec<RuntimePallet>> {
+    let (regex, declarations) = match RUNTIME_MODULE_REGEX.find(runtime) {
+        Some(mat) => (&*RUNTIME_MODULE_PALLET_REGEX, &runtime[mat.end()..]),
+        None => (
+            &*CONSTRUCT_RUNTIME_PALLET_REGEX,
+            &runtime[construct_runtime_pallets(runtime)?],
+        ),
+    };
+    let declarations = COMMENT_REGEX.replace_all(declarations, "");
+
+    let mut pallets: Vec<RuntimePallet> = Vec::new();
+    for caps in regex.captures_iter(&declarations) {
+        let explicit_index = caps.name("index").and_then(|i| i.as_str().parse().ok());
+        // Pallets without explicit index follow the previous one
+        let index =
+            explicit_index.unwrap_or_else(|| pallets.last().map_or(0, |pallet| pallet.index + 1));
+        pallets.push(RuntimePallet {
+            name: caps["name"].to_owned(),
+            path: caps["path"].to_owned(),
+            instance: match caps.name("args") {
+                // The instance follows the runtime in the generic arguments of `Pallet`
+                Some(args) => args
+                    .as_str()
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|arg| !arg.is_empty())
+                    .nth(if caps.name("pallet").is_some() { 1 } else { 0 })
+                    .map(str::to_owned),
+                None => caps.name("instance").map(|i| i.as_str().to_owned()),
+            },
+            index,
+            explicit_index: explicit_index.is_some(),
+            parts: caps.name("parts").map_or_else(Vec::new, |parts| {
+                parts
+                    .as_str()
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|part| !part.is_empty())
+                    .map(str::to_owned)
+                    .collect()
+            }),
+        });
+    }
+
+    Ok(pallets)
+}
+
+/// Path of the `lib.rs` of the runtime crate whose manifest is `manifest_path`.
+pub fn runtime_lib_path(manifest_path: &Path) -> PathBuf {
+    manifest_path
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join("src")
+        .join("lib.rs")
+}
+
+/// Find the position of the brace closing the one opened at `open`.
+pub fn matching_brace(s: &str, open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in s[open..].char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(open + i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+pub fn last_line_indent(s: &str) -> String {
+    s.lines()
+        .last()
+        .map(|line| line.chars().take_while(|c| c.is_whitespace()).collect())
+        .unwrap_or_default()
+}
+
+/// Length of the end of line & blank lines at the start of `s`.
+pub fn trailing_blank_lines(s: &str) -> usize {
+    let mut len = 0;
+    for line in s.split_inclusive('\n') {
+        if !line.trim().is_empty() || !line.ends_with('\n') {
+            break;
+        }
+        len += line.len();
+    }
+    len
+}
+
+/// Indentation unit of the source `s`, tabs unless it is indented with spaces.
+pub fn indent_unit(s: &str) -> &'static str {
+    if s.lines().any(|line| line.starts_with("    "))
+        && !s.lines().any(|line| line.starts_with('\t'))
+    {
+        "    "
+    } else {
+        "\t"
+    }
+}
+
+/// Indent the tab indented `template` by `indent`, replacing its tabs by `unit`.
+pub fn reindent(template: &str, indent: &str, unit: &str) -> String {
+    template
+        .lines()
+        .map(|line| {
+            let depth = line.chars().take_while(|c| *c == '\t').count();
+            if line.trim().is_empty() {
+                String::new()
+            } else {
+                format!("{}{}{}", indent, unit.repeat(depth), &line[depth..])
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONSTRUCT_RUNTIME: &str = "construct_runtime!(
+    pub enum Runtime where
+        Block = Block,
+        NodeBlock = opaque::Block,
+        UncheckedExtrinsic = UncheckedExtrinsic
+    {
+        System: frame_system::{Module, Call, Config, Storage, Event<T>} = 0,
+        Balances: pallet_balances::{Module, Call, Storage, Config<T>, Event<T>} = 3,
+    }
+);
+";
+
+    const RUNTIME_MODULE: &str = "#[frame_support::runtime]
+mod runtime {
+    #[runtime::runtime]
+    pub struct Runtime;
+
+    #[runtime::pallet_index(0)]
+    pub type System = frame_system;
+
+    #[runtime::pallet_index(1)]
+    pub type Balances = pallet_balances;
+}
+";
+
+    const RUNTIME_APIS: &str = "impl_runtime_apis! {
+    impl sp_api::Core<Block> for Runtime {
+        fn version() -> RuntimeVersion {
+            VERSION
+        }
+    }
+
+    impl pallet_nicks_rpc_runtime_api::NicksApi<Block> for Runtime {
+        fn nick(who: AccountId) -> Option<Vec<u8>> {
+            // Hand written
+            Nicks::nick(who)
+        }
+    }
+}
+";
+
+    fn names(runtime: &str) -> Vec<(String, u32)> {
+        runtime_pallets(runtime)
+            .unwrap()
+            .into_iter()
+            .map(|pallet| (pallet.name, pallet.index))
+            .collect()
+    }
+
+    #[test]
+    fn construct_runtime_takes_parentheses_or_braces() {
+        let braces = CONSTRUCT_RUNTIME
+            .replacen("construct_runtime!(", "construct_runtime! {", 1)
+            .replace("}\n);\n", "}\n}\n");
+        for runtime in &[CONSTRUCT_RUNTIME.to_owned(), braces] {
+            assert!(check_runtime_declaration(Path::new("lib.rs"), runtime).is_ok());
+            assert_eq!(
+                names(runtime),
+                vec![("System".to_owned(), 0), ("Balances".to_owned(), 3)]
+            );
+        }
+    }
+
+    #[test]
+    fn runtime_module_declares_the_pallets() {
+        assert!(check_runtime_declaration(Path::new("lib.rs"), RUNTIME_MODULE).is_ok());
+        assert_eq!(
+            names(RUNTIME_MODULE),
+            vec![("System".to_owned(), 0), ("Balances".to_owned(), 1)]
+        );
+        assert!(check_runtime_declaration(Path::new("lib.rs"), "pub fn noop() {}").is_err());
+    }
+
+    #[test]
+    fn runtime_module_pallets_take_generic_arguments() {
+        let runtime = RUNTIME_MODULE.replace(
+            "    #[runtime::pallet_index(1)]\n    pub type Balances = pallet_balances;\n",
+            "    #[runtime::pallet_index(1)]\n    pub type Balances = pallet_balances::Pallet<Runtime>;\n\n    #[runtime::pallet_index(2)]\n    #[runtime::disable_unsigned]\n    pub type Council = pallet_collective::Pallet<Runtime, Instance1>;\n\n    #[runtime::pallet_index(3)]\n    pub type TechnicalCommittee = pallet_collective<Instance2>;\n",
+        );
+        let pallets = runtime_pallets(&runtime)
+            .unwrap()
+            .into_iter()
+            .map(|pallet| (pallet.name, pallet.path, pallet.instance, pallet.index))
+            .collect::<Vec<_>>();
+        let pallet = |name: &str, path: &str, instance: Option<&str>, index| {
+            (
+                name.to_owned(),
+                path.to_owned(),
+                instance.map(str::to_owned),
+                index,
+            )
+        };
+        assert_eq!(
+            pallets,
+            vec![
+                pallet("System", "frame_system", None, 0),
+                pallet("Balances", "pallet_balances", None, 1),
+                pallet("Council", "pallet_collective", Some("Instance1"), 2),
+                pallet(
+                    "TechnicalCommittee",
+                    "pallet_collective",
+                    Some("Instance2"),
+                    3
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn runtime_api_is_inserted_after_the_last_implementation() {
+        let mut runtime = RUNTIME_APIS.to_owned();
+        let implementation = "impl pallet_contracts_rpc_runtime_api::ContractsApi<Block> for Runtime {\n\tfn get_storage() {}\n}";
+        assert!(insert_runtime_api(&mut runtime, implementation).unwrap());
+        assert!(runtime.ends_with(
+            "    }\n\n    impl pallet_contracts_rpc_runtime_api::ContractsApi<Block> for Runtime {\n        fn get_storage() {}\n    }\n}\n"
+        ));
+        // Once only
+        assert!(!insert_runtime_api(&mut runtime, implementation).unwrap());
+    }
+
+    #[test]
+    fn existing_runtime_api_is_kept() {
+        let mut runtime = RUNTIME_APIS.to_owned();
+        let implementation = "impl pallet_nicks_rpc_runtime_api::NicksApi<Block> for Runtime {\n\tfn nick(who: AccountId) -> Option<Vec<u8>> {\n\t\tNicks::nick(who)\n\t}\n}";
+        assert!(!insert_runtime_api(&mut runtime, implementation).unwrap());
+        assert_eq!(runtime, RUNTIME_APIS);
+    }
 
-    Ok(())
+    #[test]
+    fn runtime_api_needs_impl_runtime_apis() {
+        let mut runtime = CONSTRUCT_RUNTIME.to_owned();
+        assert!(
+            insert_runtime_api(&mut runtime, "impl sp_api::Core<Block> for Runtime {}").is_err()
+        );
+    }
 }
@@ -0,0 +1,227 @@
+//! Description of the pallets to add to a runtime, given on the command line e.g.
+//! `pallet-staking@2.0.0` or in a TOML file e.g.
+//!
+//! ```toml
+//! [pallets]
+//! pallet-timestamp = "2.0.0"
+//! pallet-session = { features = ["historical"] }
+//! council = { package = "pallet-collective", instance = 1 }
+//! pallet-kitties = { path = "../pallets/kitties" }
+//! ```
+//!
+//! The same table declares the pallets a runtime should have, in a `runtime.toml` file next to
+//! its manifest or in its `[package.metadata.substrate-deps.pallets]`.
+
+use crate::error::{CliError, CliResult};
+use crate::manifest::{DependencyKind, DependencySection};
+use crate::toml_text::TomlText;
+
+use log::debug;
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// A pallet to add to the runtime & how to add it.
+#[derive(Clone, Debug, Default)]
+pub struct PalletSpec {
+    /// Name of the pallet crate e.g. `pallet-collective`.
+    pub pallet: String,
+    /// Exact version of the pallet, defaults to the latest version.
+    pub version: Option<String>,
+    /// Directory of the local pallet crate, instead of the registry.
+    pub path: Option<PathBuf>,
+    pub alias: Option<String>,
+    pub instance: Option<u32>,
+    pub index: Option<u32>,
+    pub features: Vec<String>,
+    pub via: Option<String>,
+    pub with_rpc: bool,
+    pub section: DependencySection,
+    /// Associated types bound in the pallet trait implementation e.g. `Currency` to `Balances`.
+    pub types: BTreeMap<String, String>,
+}
+
+impl PalletSpec {
+    /// Parse a `pallet[@version]` command line argument.
+    pub fn parse(arg: &str) -> Self {
+        let mut parts = arg.splitn(2, '@');
+        Self {
+            pallet: parts.next().unwrap_or_default().to_owned(),
+            version: parts.next().map(str::to_owned),
+            ..Self::default()
+        }
+    }
+}
+
+/// A pallet declared in a TOML file as a table, rather than with its version only.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct PalletTable {
+    /// Name of the pallet crate, when the key is an alias.
+    package: Option<String>,
+    version: Option<String>,
+    path: Option<PathBuf>,
+    instance: Option<u32>,
+    index: Option<u32>,
+    features: Vec<String>,
+    via: Option<String>,
+    with_rpc: bool,
+    dev: bool,
+    build: bool,
+    target: Option<String>,
+    types: BTreeMap<String, String>,
+}
+
+/// Read the pallets declared in the `[pallets]` table of the TOML file at `path`, in the order
+/// they are declared.
+pub fn read_pallets_file(path: &Path) -> CliResult<Vec<PalletSpec>> {
+    let source = fs::read_to_string(path)?;
+    parse_pallets(&source, &["pallets"], parent_dir(path))
+}
+
+/// Path of the `runtime.toml` file declaring the pallets of the runtime whose manifest is
+/// `manifest_path`.
+pub fn runtime_declaration_path(manifest_path: &Path) -> PathBuf {
+    manifest_path
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join("runtime.toml")
+}
+
+/// Read the declaration of the pallets of the runtime whose manifest is `manifest_path`, from
+/// the `runtime.toml` file next to it, or from `[package.metadata.substrate-deps.pallets]`.
+pub fn read_runtime_declaration(manifest_path: &Path) -> CliResult<Vec<PalletSpec>> {
+    let declaration_path = runtime_declaration_path(manifest_path);
+    if declaration_path.is_file() {
+        debug!("Runtime declaration: {:?}", declaration_path);
+        return read_pallets_file(&declaration_path);
+    }
+
+    let source = fs::read_to_string(manifest_path)?;
+    let path = ["package", "metadata", "substrate-deps", "pallets"];
+    if !TomlText::parse(&source)?.contains(&path) {
+        return Err(CliError::Manifest(format!(
+            "No runtime declaration found: create {} or declare the pallets in [{}].",
+            declaration_path.display(),
+            path.join(".")
+        )));
+    }
+    parse_pallets(&source, &path, parent_dir(manifest_path))
+}
+
+/// Directory of the file at `path`, which the paths of the local pallets it declares are
+/// relative to.
+fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(dir) if dir != Path::new("") => dir,
+        _ => Path::new("."),
+    }
+}
+
+/// Parse the pallets declared in the table at `path` of the TOML `source`, in the order they are
+/// declared, resolving the paths of local pallets from the directory `dir`.
+fn parse_pallets(source: &str, path: &[&str], dir: &Path) -> CliResult<Vec<PalletSpec>> {
+    let mut value: toml::Value = toml::from_str(source)?;
+    for key in path {
+        value = match value {
+            toml::Value::Table(mut table) => table
+                .remove(*key)
+                .unwrap_or_else(|| toml::Value::Table(toml::value::Table::new())),
+            _ => return Err(CliError::Toml(format!("{} is not a table", path.join(".")))),
+        };
+    }
+    let table = match value {
+        toml::Value::Table(table) => table,
+        _ => return Err(CliError::Toml(format!("{} is not a table", path.join(".")))),
+    };
+
+    // Keep the declaration order, the keys of the parsed table being sorted
+    let order = TomlText::parse(source)?.table_keys(path);
+    let mut pallets = table.into_iter().collect::<Vec<_>>();
+    pallets.sort_by_key(|(key, _)| order.iter().position(|k| k == key));
+    pallets
+        .into_iter()
+        .map(|(key, value)| pallet_spec(key, value, dir))
+        .collect()
+}
+
+/// Build the spec of the pallet declared as `key` with `value`, the key naming the pallet crate
+/// unless the pallet declares a `package`, in which case it is the pallet alias. The path of a
+/// local pallet is relative to the directory `dir`.
+fn pallet_spec(key: String, mut value: toml::Value, dir: &Path) -> CliResult<PalletSpec> {
+    // The source & parts of the pallets written by `export` are informative only
+    if let toml::Value::Table(table) = &mut value {
+        table.remove("source");
+        table.remove("parts");
+    }
+
+    let table = match value {
+        toml::Value::String(version) => PalletTable {
+            version: Some(version),
+            ..PalletTable::default()
+        },
+        value => value
+            .try_into()
+            .map_err(|e| CliError::Toml(format!("Invalid pallet {}: {}", key, e)))?,
+    };
+
+    let kind = match (table.dev, table.build) {
+        (false, false) => DependencyKind::Normal,
+        (true, false) => DependencyKind::Development,
+        (false, true) => DependencyKind::Build,
+        (true, true) => {
+            return Err(CliError::Generic(format!(
+                "Pallet {} can't be both a dev & a build dependency.",
+                key
+            )))
+        }
+    };
+    if kind != DependencyKind::Normal
+        && (table.instance.is_some()
+            || table.index.is_some()
+            || table.via.is_some()
+            || table.with_rpc
+            || !table.types.is_empty())
+    {
+        return Err(CliError::Generic(format!(
+            "Pallet {} is not part of the runtime code, it can't have an instance, index, via, with-rpc or types.",
+            key
+        )));
+    }
+    if table.version.is_some() && table.path.is_some() {
+        return Err(CliError::Generic(format!(
+            "Pallet {} can't declare both a version & a path.",
+            key
+        )));
+    }
+    if table.via.is_some() && table.features.is_empty() {
+        return Err(CliError::Generic(format!(
+            "Pallet {} declares via without any features.",
+            key
+        )));
+    }
+
+    let (pallet, alias) = match table.package {
+        Some(package) => (package, Some(key)),
+        None => (key, None),
+    };
+    Ok(PalletSpec {
+        pallet,
+        version: table.version,
+        path: table.path.map(|path| dir.join(path)),
+        alias,
+        instance: table.instance,
+        index: table.index,
+        features: table.features,
+        via: table.via,
+        with_rpc: table.with_rpc,
+        section: DependencySection {
+            kind,
+            target: table.target,
+        },
+        types: table.types,
+    })
+}
@@ -0,0 +1,346 @@
+use crate::add::add_pallets;
+use crate::error::*;
+use crate::manifest::{
+    contains_dependency, dependency_features, dependency_key, find_crate_package,
+    find_dependency_key, find_dependency_version, set_dependency_features, set_dependency_version,
+};
+use crate::output::message;
+use crate::remove::remove_pallet;
+use crate::runtime::{
+    normalize_type, pallet_names, pallet_types, read_runtime_lib, runtime_pallets,
+    set_pallet_index, set_pallet_types, RuntimePallet,
+};
+use crate::spec::{read_pallets_file, read_runtime_declaration, PalletSpec};
+use crate::transaction::transaction;
+
+use cargo_edit::Dependency;
+use log::{debug, warn};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fmt::{Display, Formatter, Result as FmtResult},
+    path::Path,
+};
+
+/// A change converging the runtime to its declaration.
+#[derive(Debug)]
+enum Change {
+    /// Add a declared pallet missing from the runtime.
+    Add(PalletSpec),
+    /// Remove the pallet `name`, an instance of the crate `package`, which isn't declared.
+    Remove { name: String, package: String },
+    /// Change the index of the pallet `name`.
+    SetIndex { name: String, from: u32, to: u32 },
+    /// Change the version of the dependency `key`.
+    SetVersion {
+        key: String,
+        from: Option<String>,
+        to: String,
+    },
+    /// Enable & disable features of the dependency `key`, forwarded through the runtime feature
+    /// `via` if any.
+    SetFeatures {
+        key: String,
+        enable: Vec<String>,
+        disable: Vec<String>,
+        via: Option<String>,
+    },
+    /// Bind associated types in the trait implementation of `pallet`.
+    SetTypes {
+        pallet: RuntimePallet,
+        types: BTreeMap<String, String>,
+    },
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Self::Add(spec) => {
+                write!(f, "Add pallet {}", spec.pallet)?;
+                if let Some(version) = &spec.version {
+                    write!(f, " v{}", version)?;
+                }
+                if let Some(alias) = &spec.alias {
+                    write!(f, " as {}", alias)?;
+                }
+                if let Some(instance) = spec.instance {
+                    write!(f, " (instance {})", instance)?;
+                }
+                if !spec.section.is_runtime() {
+                    write!(f, " to [{}]", spec.section.path().join("."))?;
+                }
+                Ok(())
+            }
+            Self::Remove { name, package } => write!(f, "Remove pallet {} ({})", name, package),
+            Self::SetIndex { name, from, to } => {
+                write!(f, "Change pallet {} index from {} to {}", name, from, to)
+            }
+            Self::SetVersion { key, from, to } => write!(
+                f,
+                "Update dependency {} from v{} to v{}",
+                key,
+                from.as_deref().unwrap_or("?"),
+                to
+            ),
+            Self::SetFeatures {
+                key,
+                enable,
+                disable,
+                via,
+            } => {
+                write!(f, "Update dependency {} features:", key)?;
+                if !enable.is_empty() {
+                    write!(f, " enable {}", enable.join(", "))?;
+                    if let Some(via) = via {
+                        write!(f, " via {}", via)?;
+                    }
+                }
+                if !disable.is_empty() {
+                    write!(f, " disable {}", disable.join(", "))?;
+                }
+                Ok(())
+            }
+            Self::SetTypes { pallet, types } => write!(
+                f,
+                "Bind pallet {} types: {}",
+                pallet.name,
+                types
+                    .iter()
+                    .map(|(name, value)| format!("{} = {}", name, value))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+/// A change of the runtime found by `sync`.
+#[derive(Clone, Debug, Serialize)]
+pub struct SyncChange {
+    /// Description of the change e.g. `Change pallet Sudo index from 3 to 4`.
+    pub change: String,
+    /// Whether the change was applied, rather than only planned with a dry run.
+    pub applied: bool,
+}
+
+/// Converge the runtime whose manifest is `manifest_path` to the pallets declared in `file`, or in
+/// its manifest metadata, only reporting the changes if `dry_run` is set.
+pub fn execute_sync(
+    manifest_path: &Path,
+    file: Option<&Path>,
+    dry_run: bool,
+    registry: Option<&str>,
+) -> CliResult<Vec<SyncChange>> {
+    debug!("Manifest path: {:?}", manifest_path);
+    debug!("File: {:?}", file);
+    debug!("Dry run: {}", dry_run);
+    debug!("Registry: {:?}", registry);
+
+    let declared = match file {
+        Some(file) => read_pallets_file(file)?,
+        None => read_runtime_declaration(manifest_path)?,
+    };
+    debug!("Declared pallets: {:?}", declared);
+
+    let changes = plan(manifest_path, &declared)?;
+    if changes.is_empty() {
+        message("Your node runtime is in sync with its declaration.");
+        return Ok(Vec::new());
+    }
+    for change in &changes {
+        message(change);
+    }
+    let report = |applied| {
+        changes
+            .iter()
+            .map(|change| SyncChange {
+                change: change.to_string(),
+                applied,
+            })
+            .collect()
+    };
+    if dry_run {
+        return Ok(report(false));
+    }
+
+    // Restore the runtime & node files if any of the changes can't be applied
+    transaction(manifest_path, || apply(manifest_path, &changes, registry))?;
+
+    message(format!(
+        "Applied {} change(s) to your node runtime.",
+        changes.len()
+    ));
+
+    Ok(report(true))
+}
+
+/// List the changes converging the runtime to the `declared` pallets: the removals first, then
+/// the updates & the additions.
+fn plan(manifest_path: &Path, declared: &[PalletSpec]) -> CliResult<Vec<Change>> {
+    let runtime = read_runtime_lib(manifest_path)?;
+    let pallets = runtime_pallets(&runtime)?;
+
+    let mut updates = Vec::new();
+    let mut additions = Vec::new();
+    let mut names = Vec::new();
+    // Instances share their dependency, whose version & features are compared once
+    let mut keys = Vec::new();
+
+    for spec in declared {
+        // Dev & build dependencies are only declared in the manifest
+        if !spec.section.is_runtime() {
+            let key = dependency_key(spec.alias.as_deref().unwrap_or(&spec.pallet));
+            if !contains_dependency(manifest_path, &spec.section, &key)? {
+                additions.push(Change::Add(spec.clone()));
+            }
+            continue;
+        }
+
+        let dependency = Dependency::new(&spec.pallet);
+        let (_, name) = pallet_names(
+            manifest_path,
+            &dependency,
+            &spec.alias.as_deref(),
+            spec.instance,
+        )?;
+        if names.contains(&name) {
+            return Err(CliError::Generic(format!(
+                "Pallet {} is declared more than once.",
+                name
+            )));
+        }
+        names.push(name.clone());
+
+        let pallet = match pallets.iter().find(|pallet| pallet.name == name) {
+            Some(pallet) => pallet,
+            None => {
+                additions.push(Change::Add(spec.clone()));
+                continue;
+            }
+        };
+
+        if let Some(index) = spec.index {
+            if index != pallet.index || !pallet.explicit_index {
+                updates.push(Change::SetIndex {
+                    name: name.clone(),
+                    from: pallet.index,
+                    to: index,
+                });
+            }
+        }
+
+        let bound = pallet_types(&runtime, pallet)?;
+        let types = spec
+            .types
+            .iter()
+            .filter(|(ty, value)| bound.get(*ty) != Some(&normalize_type(value)))
+            .map(|(ty, value)| (ty.clone(), value.clone()))
+            .collect::<BTreeMap<_, _>>();
+        if !types.is_empty() {
+            updates.push(Change::SetTypes {
+                pallet: pallet.clone(),
+                types,
+            });
+        }
+
+        let key = match find_dependency_key(manifest_path, &spec.pallet)? {
+            Some(key) if !keys.contains(&key) => key,
+            _ => continue,
+        };
+        keys.push(key.clone());
+
+        if let Some(version) = &spec.version {
+            let from = find_dependency_version(manifest_path, &key)?;
+            if from.as_ref() != Some(version) {
+                updates.push(Change::SetVersion {
+                    key: key.clone(),
+                    from,
+                    to: version.clone(),
+                });
+            }
+        }
+
+        let features = dependency_features(manifest_path, &key)?;
+        let enabled = match &spec.via {
+            Some(via) => features
+                .forwarded
+                .into_iter()
+                .filter(|(runtime_feature, _)| runtime_feature == via)
+                .map(|(_, feature)| feature)
+                .collect(),
+            None => features.enabled,
+        };
+        let enable = spec
+            .features
+            .iter()
+            .filter(|feature| !enabled.contains(feature))
+            .cloned()
+            .collect::<Vec<_>>();
+        let disable = enabled
+            .iter()
+            .filter(|feature| !spec.features.contains(feature))
+            .cloned()
+            .collect::<Vec<_>>();
+        if !enable.is_empty() || !disable.is_empty() {
+            updates.push(Change::SetFeatures {
+                key,
+                enable,
+                disable,
+                via: spec.via.clone(),
+            });
+        }
+    }
+
+    let mut changes = Vec::new();
+    for pallet in &pallets {
+        if names.contains(&pallet.name) {
+            continue;
+        }
+        match find_crate_package(manifest_path, &pallet.path)? {
+            Some(package) => changes.push(Change::Remove {
+                name: pallet.name.clone(),
+                package,
+            }),
+            None => warn!(
+                "Pallet {} isn't declared, but its crate isn't a dependency of your node runtime: skipping.",
+                pallet.name
+            ),
+        }
+    }
+    changes.extend(updates);
+    changes.extend(additions);
+
+    Ok(changes)
+}
+
+/// Apply the `changes` to the runtime, adding the missing pallets at once.
+fn apply(manifest_path: &Path, changes: &[Change], registry: Option<&str>) -> CliResult<()> {
+    let mut additions = Vec::new();
+    for change in changes {
+        match change {
+            Change::Add(spec) => additions.push(spec.clone()),
+            Change::Remove { name, package } => {
+                remove_pallet(manifest_path, package, name)?;
+            }
+            Change::SetIndex { name, to, .. } => {
+                set_pallet_index(manifest_path, name, *to)?;
+            }
+            Change::SetVersion { key, to, .. } => set_dependency_version(manifest_path, key, to)?,
+            Change::SetFeatures {
+                key,
+                enable,
+                disable,
+                via,
+            } => set_dependency_features(manifest_path, key, enable, disable, via.as_deref())?,
+            Change::SetTypes { pallet, types } => {
+                set_pallet_types(manifest_path, pallet, types)?;
+            }
+        }
+    }
+
+    if !additions.is_empty() {
+        add_pallets(manifest_path, &additions, registry)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,952 @@
+//! Edit TOML documents such as manifests with `toml_edit`, addressing their items by path however
+//! they are declared (table headers, dotted keys or inline tables), and following the existing
+//! formatting: comments, quote style, alignment & array layout.
+
+use crate::error::CliResult;
+
+use std::fmt;
+use toml_edit::{Array, DocumentMut, InlineTable, Item, Table, TableLike};
+
+/// A value to insert in a TOML document.
+#[derive(Clone, Debug)]
+pub enum Value {
+    String(String),
+    Boolean(bool),
+    Array(Vec<String>),
+}
+
+impl Value {
+    fn to_toml(&self, quote: char) -> toml_edit::Value {
+        match self {
+            Value::String(s) => string(s, quote),
+            Value::Boolean(b) => (*b).into(),
+            Value::Array(a) => {
+                let mut array = Array::new();
+                for s in a {
+                    array.push(string(s, quote));
+                }
+                array.into()
+            }
+        }
+    }
+}
+
+/// Render the `fields` as an inline table e.g. `{ version = "2.0.0", default-features = false }`.
+pub fn inline_table(fields: &[(&str, Value)]) -> String {
+    let mut table = InlineTable::new();
+    for (name, value) in fields {
+        table.insert(*name, value.to_toml('"'));
+    }
+    table.fmt();
+    table.to_string()
+}
+
+/// How a table is declared in a TOML document.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TableStyle {
+    /// `name = { key = value }`
+    Inline,
+    /// `[parent.name]`
+    Header,
+    /// `name.key = value`
+    Dotted,
+}
+
+/// A TOML document, edited in place.
+#[derive(Clone, Debug)]
+pub struct TomlText {
+    document: DocumentMut,
+}
+
+impl fmt::Display for TomlText {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.document.fmt(f)
+    }
+}
+
+impl TomlText {
+    pub fn parse(source: &str) -> CliResult<Self> {
+        Ok(Self {
+            document: source.parse()?,
+        })
+    }
+
+    /// List the keys of the table at `path`, whether it is declared with a header, dotted keys
+    /// or as an inline table.
+    pub fn table_keys(&self, path: &[&str]) -> Vec<String> {
+        self.table(path)
+            .map(|table| table.iter().map(|(key, _)| key.to_owned()).collect())
+            .unwrap_or_default()
+    }
+
+    /// List the string values of the array at `path`, if any.
+    pub fn array(&self, path: &[&str]) -> Option<Vec<String>> {
+        let array = self.item(path)?.as_array()?;
+        Some(
+            array
+                .iter()
+                .filter_map(|value| value.as_str().map(str::to_owned))
+                .collect(),
+        )
+    }
+
+    /// Add the string `entry` to the array at `path` unless it already contains it, creating the
+    /// array & its tables if needed. Returns whether the document was updated.
+    pub fn insert_into_array(&mut self, path: &[&str], entry: &str) -> bool {
+        let (key, parent) = match path.split_last() {
+            Some(split) => split,
+            None => return false,
+        };
+        let quote = self.quote_style(parent);
+
+        if self.contains(path) {
+            let array = match self.item_mut(path).and_then(Item::as_array_mut) {
+                Some(array) => array,
+                None => return false,
+            };
+            if array.iter().any(|value| value.as_str() == Some(entry)) {
+                return false;
+            }
+            let quote = array.iter().find_map(quote_style).unwrap_or(quote);
+            push(array, string(entry, quote));
+            return true;
+        }
+
+        let mut array = Array::new();
+        array.push(string(entry, quote));
+        match self.table_or_insert(parent) {
+            Some(table) => table.insert(key, toml_edit::value(array)).is_none(),
+            None => false,
+        }
+    }
+
+    /// Remove the string entries matching `predicate` from the array at `path`. Returns whether
+    /// the document was updated.
+    pub fn remove_from_array(&mut self, path: &[&str], predicate: impl Fn(&str) -> bool) -> bool {
+        let array = match self.item_mut(path).and_then(Item::as_array_mut) {
+            Some(array) => array,
+            None => return false,
+        };
+        let mut updated = false;
+        loop {
+            let index = array
+                .iter()
+                .position(|value| matches!(value.as_str(), Some(entry) if predicate(entry)));
+            match index {
+                Some(index) => remove(array, index),
+                None => return updated,
+            }
+            updated = true;
+        }
+    }
+
+    /// Whether the document declares a value or a table at `path`.
+    pub fn contains(&self, path: &[&str]) -> bool {
+        self.item(path).is_some()
+    }
+
+    /// The string value at `path`, if any.
+    pub fn string(&self, path: &[&str]) -> Option<String> {
+        self.item(path)?.as_str().map(str::to_owned)
+    }
+
+    /// Replace the string value at `path` by `value`, keeping its quote style & decor. Returns
+    /// whether the document was updated.
+    pub fn set_string(&mut self, path: &[&str], value: &str) -> bool {
+        let current = match self.item_mut(path).and_then(Item::as_value_mut) {
+            Some(current) if current.is_str() => current,
+            _ => return false,
+        };
+        if current.as_str() == Some(value) {
+            return false;
+        }
+        let mut replacement = string(value, quote_style(current).unwrap_or('"'));
+        *replacement.decor_mut() = current.decor().clone();
+        *current = replacement;
+        true
+    }
+
+    /// The boolean value at `path`, if any.
+    pub fn boolean(&self, path: &[&str]) -> Option<bool> {
+        self.item(path)?.as_bool()
+    }
+
+    /// Remove the value or table at `path`, however it is declared. Returns whether the document
+    /// was updated.
+    pub fn remove(&mut self, path: &[&str]) -> bool {
+        match path.split_last() {
+            Some((key, parent)) => self
+                .table_mut(parent)
+                .and_then(|table| table.remove(key))
+                .is_some(),
+            None => false,
+        }
+    }
+
+    /// Insert the table `key` with the `fields` in the table at `parent`, unless it already
+    /// exists. The table is declared like most of its sibling tables, either inline, with a
+    /// header or with dotted keys, following their quote style, the order of their fields &
+    /// their alignment. Returns whether the document was updated.
+    pub fn insert_table(&mut self, parent: &[&str], key: &str, fields: &[(&str, Value)]) -> bool {
+        let path = parent.iter().copied().chain(Some(key)).collect::<Vec<_>>();
+        if self.contains(&path) {
+            return false;
+        }
+
+        let siblings = self.table_keys(parent);
+        let style = self.table_style(parent);
+        let quote = self.quote_style(parent);
+        let fields = self.sibling_field_order(parent, fields);
+        let key_width = self.key_width(parent);
+        let field_width = match style {
+            TableStyle::Header if self.header_fields_aligned(parent) => {
+                fields.iter().map(|(name, _)| name.len() + 1).max()
+            }
+            _ => None,
+        };
+
+        let item = match style {
+            TableStyle::Inline => {
+                let mut table = InlineTable::new();
+                for (name, value) in &fields {
+                    table.insert(name, value.to_toml(quote));
+                }
+                table.fmt();
+                toml_edit::value(table)
+            }
+            TableStyle::Header | TableStyle::Dotted => {
+                let mut table = Table::new();
+                table.set_dotted(style == TableStyle::Dotted);
+                for (name, value) in &fields {
+                    table.insert(name, toml_edit::value(value.to_toml(quote)));
+                    if let (Some(width), Some(mut field)) = (field_width, table.key_mut(name)) {
+                        let padding = " ".repeat(width - name.len());
+                        field.leaf_decor_mut().set_suffix(padding);
+                    }
+                }
+                Item::Table(table)
+            }
+        };
+
+        let table = match self.table_or_insert(parent) {
+            Some(table) => table,
+            None => return false,
+        };
+        table.insert(key, item);
+        if let (TableStyle::Inline, Some(width), Some(mut new_key)) =
+            (style, key_width, table.key_mut(key))
+        {
+            let padding = " ".repeat(width.saturating_sub(key.len()).max(1));
+            new_key.leaf_decor_mut().set_suffix(padding);
+        }
+        // Keep the sibling tables sorted, if they are, but not the fields of the dotted ones
+        if siblings.len() > 1 && siblings.windows(2).all(|pair| pair[0] <= pair[1]) {
+            let order = |a: &toml_edit::Key, b: &toml_edit::Key| {
+                let sibling =
+                    |k: &toml_edit::Key| k.get() == key || siblings.contains(&k.get().to_owned());
+                if sibling(a) && sibling(b) {
+                    a.get().cmp(b.get())
+                } else {
+                    std::cmp::Ordering::Equal
+                }
+            };
+            match self.item_mut(parent) {
+                Some(Item::Table(table)) => table.sort_values_by(|a, _, b, _| order(a, b)),
+                Some(Item::Value(toml_edit::Value::InlineTable(table))) => {
+                    table.sort_values_by(|a, _, b, _| order(a, b))
+                }
+                _ => {}
+            }
+        }
+        true
+    }
+
+    fn item(&self, path: &[&str]) -> Option<&Item> {
+        path.iter()
+            .try_fold(self.document.as_item(), |item, key| item.get(key))
+    }
+
+    /// The item at `path`, without inserting the missing ones as `Item::get_mut` does.
+    fn item_mut(&mut self, path: &[&str]) -> Option<&mut Item> {
+        path.iter()
+            .try_fold(self.document.as_item_mut(), |item, key| {
+                item.as_table_like_mut()?.get_mut(key)
+            })
+    }
+
+    fn table(&self, path: &[&str]) -> Option<&dyn TableLike> {
+        self.item(path)?.as_table_like()
+    }
+
+    fn table_mut(&mut self, path: &[&str]) -> Option<&mut dyn TableLike> {
+        self.item_mut(path)?.as_table_like_mut()
+    }
+
+    /// The table at `path`, inserting the missing tables with headers. Returns `None` if a value
+    /// which isn't a table is declared on the way.
+    fn table_or_insert(&mut self, path: &[&str]) -> Option<&mut dyn TableLike> {
+        let mut item = self.document.as_item_mut();
+        for (i, key) in path.iter().enumerate() {
+            let table = item.as_table_like_mut()?;
+            if !table.contains_key(key) {
+                let mut missing = Table::new();
+                // Only declare the headers of the tables holding values
+                missing.set_implicit(i + 1 < path.len());
+                table.insert(key, Item::Table(missing));
+            }
+            item = table.get_mut(key)?;
+        }
+        item.as_table_like_mut()
+    }
+
+    /// Style of most of the tables in the table at `parent`, preferring inline tables.
+    fn table_style(&self, parent: &[&str]) -> TableStyle {
+        let mut counts = [
+            (TableStyle::Inline, 0),
+            (TableStyle::Header, 0),
+            (TableStyle::Dotted, 0),
+        ];
+        for (_, item) in self
+            .table(parent)
+            .into_iter()
+            .flat_map(|table| table.iter())
+        {
+            let style = match item {
+                Item::Value(toml_edit::Value::InlineTable(_)) => TableStyle::Inline,
+                Item::Table(table) if table.is_dotted() => TableStyle::Dotted,
+                Item::Table(_) => TableStyle::Header,
+                _ => continue,
+            };
+            for count in counts.iter_mut().filter(|(s, _)| *s == style) {
+                count.1 += 1;
+            }
+        }
+        counts
+            .iter()
+            .rev()
+            .max_by_key(|(_, count)| *count)
+            .map_or(TableStyle::Inline, |(style, _)| *style)
+    }
+
+    /// Order the `fields` like the tables in the table at `parent` do, on average.
+    fn sibling_field_order(
+        &self,
+        parent: &[&str],
+        fields: &[(&str, Value)],
+    ) -> Vec<(String, Value)> {
+        let siblings = self
+            .table(parent)
+            .into_iter()
+            .flat_map(|table| table.iter())
+            .filter_map(|(_, item)| item.as_table_like())
+            .map(|table| table.iter().map(|(key, _)| key).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let mut fields = fields
+            .iter()
+            .enumerate()
+            .map(|(i, (name, value))| {
+                let positions = siblings
+                    .iter()
+                    .filter_map(|keys| {
+                        keys.iter()
+                            .position(|key| key == name)
+                            .map(|position| position as f64 / keys.len() as f64)
+                    })
+                    .collect::<Vec<_>>();
+                let rank = if positions.is_empty() {
+                    i as f64 / fields.len() as f64
+                } else {
+                    positions.iter().sum::<f64>() / positions.len() as f64
+                };
+                (rank, (name.to_string(), value.clone()))
+            })
+            .collect::<Vec<_>>();
+        fields.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        fields.into_iter().map(|(_, field)| field).collect()
+    }
+
+    /// Width of the keys of the table at `path` up to their values, if they are aligned.
+    fn key_width(&self, path: &[&str]) -> Option<usize> {
+        self.table(path).and_then(aligned_width)
+    }
+
+    /// Whether the fields of the tables declared with headers in the table at `parent` are
+    /// aligned.
+    fn header_fields_aligned(&self, parent: &[&str]) -> bool {
+        let mut headers = self
+            .table(parent)
+            .into_iter()
+            .flat_map(|table| table.iter())
+            .filter_map(|(_, item)| match item {
+                Item::Table(table) if !table.is_dotted() && table.len() > 1 => Some(table),
+                _ => None,
+            })
+            .peekable();
+        headers.peek().is_some() && headers.all(|table| aligned_width(table).is_some())
+    }
+
+    /// Predominant quote style of the strings in the table at `path`, or else in the document.
+    fn quote_style(&self, path: &[&str]) -> char {
+        let mut counts = (0, 0);
+        if let Some(item) = self.item(path) {
+            count_quotes(item, &mut counts);
+        }
+        if counts == (0, 0) {
+            count_quotes(self.document.as_item(), &mut counts);
+        }
+        if counts.1 > counts.0 {
+            '\''
+        } else {
+            '"'
+        }
+    }
+}
+
+/// Width of the keys of `table` declared on their own line up to their values, if there are
+/// several and they are aligned with more than one space.
+fn aligned_width(table: &dyn TableLike) -> Option<usize> {
+    let widths = table
+        .iter()
+        .filter(|(_, item)| item.is_value())
+        .filter_map(|(key, _)| {
+            let key = table.key(key)?;
+            let suffix = key.leaf_decor().suffix().and_then(|s| s.as_str())?;
+            Some((key.display_repr().len() + suffix.len(), suffix.len()))
+        })
+        .collect::<Vec<_>>();
+    let width = widths.first()?.0;
+    let aligned = widths.len() > 1
+        && widths.iter().all(|(w, _)| *w == width)
+        && widths.iter().any(|(_, suffix)| *suffix > 1);
+    if aligned {
+        Some(width)
+    } else {
+        None
+    }
+}
+
+/// Count the strings quoted with double & single quotes in `item`.
+fn count_quotes(item: &Item, counts: &mut (usize, usize)) {
+    fn count_value(value: &toml_edit::Value, counts: &mut (usize, usize)) {
+        match value {
+            toml_edit::Value::Array(array) => array.iter().for_each(|v| count_value(v, counts)),
+            toml_edit::Value::InlineTable(table) => {
+                table.iter().for_each(|(_, v)| count_value(v, counts))
+            }
+            _ => match quote_style(value) {
+                Some('"') => counts.0 += 1,
+                Some(_) => counts.1 += 1,
+                None => {}
+            },
+        }
+    }
+    match item {
+        Item::Value(value) => count_value(value, counts),
+        Item::Table(table) => table
+            .iter()
+            .for_each(|(_, item)| count_quotes(item, counts)),
+        Item::ArrayOfTables(tables) => tables
+            .iter()
+            .flat_map(|table| table.iter())
+            .for_each(|(_, item)| count_quotes(item, counts)),
+        Item::None => {}
+    }
+}
+
+/// Quote of the string `value` as declared in the document, if it is a string.
+fn quote_style(value: &toml_edit::Value) -> Option<char> {
+    match value {
+        toml_edit::Value::String(s) => s.as_repr()?.as_raw().as_str()?.chars().next(),
+        _ => None,
+    }
+}
+
+/// The string `s`, quoted with `quote` when possible.
+fn string(s: &str, quote: char) -> toml_edit::Value {
+    if quote == '\'' && !s.contains(|c: char| c == '\'' || c.is_control()) {
+        if let Ok(value) = format!("'{}'", s).parse() {
+            return value;
+        }
+    }
+    s.into()
+}
+
+fn decor_str(s: Option<&toml_edit::RawString>) -> &str {
+    s.and_then(|s| s.as_str()).unwrap_or("")
+}
+
+/// Split `s` at its last newline, after it.
+fn split_last_line(s: &str) -> Option<(&str, &str)> {
+    s.rfind('\n').map(|i| s.split_at(i + 1))
+}
+
+/// Split `s` at its first newline, before it, e.g. between the comment ending the line of the
+/// previous element of a multi-line array & the lines before the next element.
+fn split_first_line(s: &str) -> Option<(&str, &str)> {
+    s.find('\n').map(|i| s.split_at(i))
+}
+
+/// Append `value` to `array`, laid out like its last element, before the comments following it.
+fn push(array: &mut Array, mut value: toml_edit::Value) {
+    let last_prefix = match array.iter().last() {
+        Some(last) => decor_str(last.decor().prefix()).to_owned(),
+        None => return array.push(value),
+    };
+    if !last_prefix.contains('\n') {
+        return array.push(value);
+    }
+
+    // Multi-line array: the comments & the newline after the last element are in the trailing
+    // of the array when it ends with a comma, or else in the suffix of the last element
+    let indent = split_last_line(&last_prefix).map_or("", |(_, indent)| indent);
+    let trailing_comma = array.trailing_comma();
+    let after = if trailing_comma {
+        decor_str(Some(array.trailing())).to_owned()
+    } else {
+        let last = array.get_mut(array.len() - 1).expect("not empty");
+        let suffix = decor_str(last.decor().suffix()).to_owned();
+        last.decor_mut().set_suffix("");
+        suffix
+    };
+    let (before, closing) = match split_last_line(&after) {
+        Some((comments, closing)) => (format!("{}{}", comments, indent), format!("\n{}", closing)),
+        None => (last_prefix.clone(), after.clone()),
+    };
+    value.decor_mut().set_prefix(before);
+    if trailing_comma {
+        if after.contains('\n') {
+            array.set_trailing(closing);
+        }
+        value.decor_mut().set_suffix("");
+    } else {
+        value.decor_mut().set_suffix(closing);
+    }
+    array.push_formatted(value);
+}
+
+/// Remove the element at `index` from `array`, with the comment ending its line, and keeping the
+/// layout of the other elements.
+fn remove(array: &mut Array, index: usize) {
+    let removed = array.remove(index);
+    let prefix = decor_str(removed.decor().prefix());
+    let suffix = decor_str(removed.decor().suffix());
+    // Comment ending the line of the previous element, if any
+    let previous_comment = split_first_line(prefix).map_or("", |(comment, _)| comment);
+
+    if let Some(next) = array.get_mut(index) {
+        let next_prefix = decor_str(next.decor().prefix());
+        let next_prefix = match split_first_line(next_prefix) {
+            Some((_, lines)) => format!("{}{}", previous_comment, lines),
+            None if index == 0 => prefix.to_owned(),
+            None => next_prefix.to_owned(),
+        };
+        next.decor_mut().set_prefix(next_prefix);
+    } else if array.trailing_comma() {
+        let trailing = format!("{}{}", previous_comment, decor_str(Some(array.trailing())));
+        array.set_trailing(trailing);
+    } else if let Some(last) = index.checked_sub(1).and_then(|i| array.get_mut(i)) {
+        last.decor_mut()
+            .set_suffix(format!("{}{}", previous_comment, suffix));
+    } else if array.is_empty() {
+        array.set_trailing_comma(false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(source: &str) -> TomlText {
+        TomlText::parse(source).unwrap()
+    }
+
+    fn version(v: &str) -> [(&str, Value); 2] {
+        [
+            ("version", Value::String(v.to_owned())),
+            ("default-features", Value::Boolean(false)),
+        ]
+    }
+
+    #[test]
+    fn inline_tables_get_inline_siblings() {
+        let mut toml = text(
+            "[dependencies]\n\
+             frame-support = { version = \"2.0.0\", default-features = false }\n\
+             pallet-sudo = { version = \"2.0.0\", default-features = false }\n",
+        );
+        assert!(toml.insert_table(&["dependencies"], "pallet-nicks", &version("2.0.0")));
+        assert_eq!(
+            toml.to_string(),
+            "[dependencies]\n\
+             frame-support = { version = \"2.0.0\", default-features = false }\n\
+             pallet-nicks = { version = \"2.0.0\", default-features = false }\n\
+             pallet-sudo = { version = \"2.0.0\", default-features = false }\n"
+        );
+        assert!(!toml.insert_table(&["dependencies"], "pallet-nicks", &version("3.0.0")));
+    }
+
+    #[test]
+    fn standard_tables_get_standard_siblings() {
+        let mut toml = text(
+            "[dependencies.frame-support]\n\
+             version = \"2.0.0\"\n\
+             default-features = false\n\
+             \n\
+             [dependencies.pallet-sudo]\n\
+             version = \"2.0.0\"\n\
+             default-features = false\n",
+        );
+        assert!(toml.insert_table(&["dependencies"], "pallet-nicks", &version("2.0.0")));
+        assert_eq!(
+            toml.to_string(),
+            "[dependencies.frame-support]\n\
+             version = \"2.0.0\"\n\
+             default-features = false\n\
+             \n\
+             [dependencies.pallet-nicks]\n\
+             version = \"2.0.0\"\n\
+             default-features = false\n\
+             \n\
+             [dependencies.pallet-sudo]\n\
+             version = \"2.0.0\"\n\
+             default-features = false\n"
+        );
+        assert_eq!(
+            toml.table_keys(&["dependencies"]),
+            ["frame-support", "pallet-nicks", "pallet-sudo"]
+        );
+    }
+
+    #[test]
+    fn dotted_keys_are_read_and_extended() {
+        let mut toml = text(
+            "[dependencies]\n\
+             frame-support.version = \"2.0.0\"\n\
+             frame-support.default-features = false\n\
+             pallet-sudo.version = \"2.0.0\"\n\
+             pallet-sudo.default-features = false\n",
+        );
+        assert_eq!(
+            toml.string(&["dependencies", "pallet-sudo", "version"]),
+            Some("2.0.0".to_owned())
+        );
+        assert_eq!(
+            toml.boolean(&["dependencies", "pallet-sudo", "default-features"]),
+            Some(false)
+        );
+        assert!(toml.insert_table(&["dependencies"], "pallet-nicks", &version("2.0.0")));
+        assert!(toml.to_string().contains(
+            "frame-support.default-features = false\n\
+             pallet-nicks.version = \"2.0.0\"\n\
+             pallet-nicks.default-features = false\n\
+             pallet-sudo.version"
+        ));
+
+        assert!(toml.remove(&["dependencies", "pallet-sudo"]));
+        assert!(!toml.contains(&["dependencies", "pallet-sudo"]));
+        assert!(toml.contains(&["dependencies", "pallet-nicks", "version"]));
+    }
+
+    #[test]
+    fn array_comments_and_trailing_commas_are_kept() {
+        let mut toml = text(
+            "[features]\n\
+             std = [\n    \
+             \"codec/std\", # the codec\n    \
+             \"frame-support/std\", # the support\n\
+             ]\n",
+        );
+        assert!(toml.insert_into_array(&["features", "std"], "pallet-sudo/std"));
+        assert_eq!(
+            toml.to_string(),
+            "[features]\n\
+             std = [\n    \
+             \"codec/std\", # the codec\n    \
+             \"frame-support/std\", # the support\n    \
+             \"pallet-sudo/std\",\n\
+             ]\n"
+        );
+        assert!(!toml.insert_into_array(&["features", "std"], "codec/std"));
+
+        assert!(toml.remove_from_array(&["features", "std"], |entry| entry == "codec/std"));
+        assert_eq!(
+            toml.array(&["features", "std"]),
+            Some(vec![
+                "frame-support/std".to_owned(),
+                "pallet-sudo/std".to_owned()
+            ])
+        );
+        assert!(!toml.to_string().contains("# the codec"));
+        assert!(toml.remove_from_array(&["features", "std"], |entry| entry == "pallet-sudo/std"));
+        assert_eq!(
+            toml.to_string(),
+            "[features]\n\
+             std = [\n    \
+             \"frame-support/std\", # the support\n\
+             ]\n"
+        );
+    }
+
+    #[test]
+    fn arrays_without_trailing_comma_stay_without() {
+        let mut toml = text("[features]\nstd = [\n    \"codec/std\",\n    \"system/std\"\n]\n");
+        assert!(toml.insert_into_array(&["features", "std"], "pallet-sudo/std"));
+        assert_eq!(
+            toml.to_string(),
+            "[features]\nstd = [\n    \"codec/std\",\n    \"system/std\",\n    \"pallet-sudo/std\"\n]\n"
+        );
+        assert!(toml.remove_from_array(&["features", "std"], |entry| entry == "pallet-sudo/std"));
+        assert_eq!(
+            toml.to_string(),
+            "[features]\nstd = [\n    \"codec/std\",\n    \"system/std\"\n]\n"
+        );
+
+        let mut toml = text("[features]\nstd = [\"codec/std\", \"system/std\"]\n");
+        assert!(toml.insert_into_array(&["features", "std"], "pallet-sudo/std"));
+        assert_eq!(
+            toml.to_string(),
+            "[features]\nstd = [\"codec/std\", \"system/std\", \"pallet-sudo/std\"]\n"
+        );
+        assert!(toml.remove_from_array(&["features", "std"], |entry| entry != "system/std"));
+        assert_eq!(toml.to_string(), "[features]\nstd = [\"system/std\"]\n");
+    }
+
+    #[test]
+    fn quoted_keys_are_unquoted() {
+        let toml = text(
+            "[dependencies]\n\
+             \"pallet-sudo\" = { version = \"2.0.0\" }\n\
+             'dotted.name' = { version = \"1.0.0\" }\n\
+             \n\
+             [target.'cfg(unix)'.dependencies]\n\
+             pallet-nicks = \"2.0.0\"\n",
+        );
+        assert_eq!(
+            toml.string(&["dependencies", "pallet-sudo", "version"]),
+            Some("2.0.0".to_owned())
+        );
+        assert_eq!(
+            toml.string(&["dependencies", "dotted.name", "version"]),
+            Some("1.0.0".to_owned())
+        );
+        assert_eq!(
+            toml.string(&["target", "cfg(unix)", "dependencies", "pallet-nicks"]),
+            Some("2.0.0".to_owned())
+        );
+    }
+
+    #[test]
+    fn quote_style_is_kept() {
+        let mut toml = text(
+            "[dependencies]\n\
+             pallet-sudo = { version = '2.0.0', default-features = false }\n\
+             \n\
+             [features]\n\
+             std = ['pallet-sudo/std']\n",
+        );
+        assert!(toml.set_string(&["dependencies", "pallet-sudo", "version"], "3.0.0"));
+        assert!(toml.insert_table(&["dependencies"], "pallet-nicks", &version("3.0.0")));
+        assert!(toml.insert_into_array(&["features", "std"], "pallet-nicks/std"));
+        assert!(toml.insert_into_array(&["features", "runtime-benchmarks"], "a/b"));
+        assert_eq!(
+            toml.to_string(),
+            "[dependencies]\n\
+             pallet-sudo = { version = '3.0.0', default-features = false }\n\
+             pallet-nicks = { version = '3.0.0', default-features = false }\n\
+             \n\
+             [features]\n\
+             std = ['pallet-sudo/std', 'pallet-nicks/std']\n\
+             runtime-benchmarks = ['a/b']\n"
+        );
+    }
+
+    #[test]
+    fn missing_features_table_is_created() {
+        let mut toml = text("[package]\nname = \"node-runtime\"\n\n[dependencies]\n");
+        assert_eq!(toml.array(&["features", "std"]), None);
+        assert!(toml.insert_into_array(&["features", "std"], "pallet-sudo/std"));
+        assert_eq!(
+            toml.to_string(),
+            "[package]\nname = \"node-runtime\"\n\n[dependencies]\n\n[features]\nstd = [\"pallet-sudo/std\"]\n"
+        );
+
+        // An existing table gets the missing array, whatever its style
+        assert!(toml.insert_into_array(&["features", "runtime-benchmarks"], "a/b"));
+        assert_eq!(
+            toml.array(&["features", "runtime-benchmarks"]),
+            Some(vec!["a/b".to_owned()])
+        );
+        let mut toml = text("[package]\nname = \"node-runtime\"\nfeatures.default = []\n");
+        assert!(toml.insert_into_array(&["package", "features", "std"], "a/std"));
+        assert!(toml
+            .to_string()
+            .ends_with("features.default = []\nfeatures.std = [\"a/std\"]\n"));
+
+        // Values which aren't arrays are left as is
+        let mut toml = text("[features]\nstd = \"codec/std\"\n");
+        assert!(!toml.insert_into_array(&["features", "std"], "a/std"));
+        assert_eq!(toml.array(&["features", "std"]), None);
+    }
+
+    #[test]
+    fn edits_match_a_toml_parser() {
+        let sources = [
+            "[dependencies]\nsudo = { package = \"pallet-sudo\", version = \"2.0.0\" }\n\n[features]\nstd = [\"sudo/std\"]\n",
+            "[dependencies.sudo]\npackage = 'pallet-sudo'\nversion = '2.0.0'\n\n[features]\nstd = [\n    'sudo/std', # sudo\n]\n",
+            "[dependencies]\nsudo.package = \"pallet-sudo\"\nsudo.version = \"2.0.0\"\n[features]\nstd = []\n",
+            "[package]\nname = \"runtime\"\n[dependencies]\n",
+        ];
+        for source in &sources {
+            let mut toml = text(source);
+            toml.insert_table(&["dependencies"], "pallet-nicks", &version("2.0.0"));
+            toml.insert_into_array(&["features", "std"], "pallet-nicks/std");
+            toml.remove_from_array(&["features", "std"], |entry| entry == "sudo/std");
+            toml.remove(&["dependencies", "sudo"]);
+
+            let edited = toml.to_string();
+            let parsed = edited
+                .parse::<toml::Value>()
+                .unwrap_or_else(|e| panic!("{}\n{}", e, edited));
+            let expected = r#"
+                [dependencies]
+                pallet-nicks = { version = "2.0.0", default-features = false }
+                [features]
+                std = ["pallet-nicks/std"]
+            "#
+            .parse::<toml::Value>()
+            .unwrap();
+            assert_eq!(
+                parsed["dependencies"], expected["dependencies"],
+                "{}",
+                edited
+            );
+            assert_eq!(parsed["features"], expected["features"], "{}", edited);
+        }
+    }
+
+    #[test]
+    fn key_alignment_is_kept() {
+        let mut toml = text(
+            "[dependencies]\n\
+             codec         = { package = \"parity-scale-codec\", version = \"1.3.4\" }\n\
+             pallet-sudo   = { version = \"2.0.0\" }\n",
+        );
+        assert!(toml.insert_table(
+            &["dependencies"],
+            "pallet-nicks",
+            &[("version", Value::String("2.0.0".to_owned()))]
+        ));
+        assert!(toml
+            .to_string()
+            .contains("\npallet-nicks  = { version = \"2.0.0\" }\npallet-sudo   ="));
+
+        let mut toml = text(
+            "[dependencies.pallet-sudo]\n\
+             version          = \"2.0.0\"\n\
+             default-features = false\n",
+        );
+        assert!(toml.insert_table(&["dependencies"], "pallet-nicks", &version("2.0.0")));
+        assert!(toml.to_string().contains(
+            "[dependencies.pallet-nicks]\n\
+             version          = \"2.0.0\"\n\
+             default-features = false\n"
+        ));
+    }
+
+    /// Add a pallet to the runtime `manifest` the way `substrate-deps add` does, checking the
+    /// rest of the manifest is left as is.
+    fn add_pallet(manifest: &str, fields: &[(&str, Value)]) -> String {
+        let mut toml = text(manifest);
+        assert_eq!(toml.to_string(), manifest);
+        assert!(toml.insert_table(&["dependencies"], "pallet-nicks", fields));
+        assert!(toml.insert_into_array(&["features", "std"], "pallet-nicks/std"));
+
+        let edited = toml.to_string();
+        let mut expected = manifest.parse::<toml::Value>().unwrap();
+        let parsed = edited
+            .parse::<toml::Value>()
+            .unwrap_or_else(|e| panic!("{}\n{}", e, edited));
+        let table = fields
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_toml('"').to_string()))
+            .map(|(name, value)| format!("{} = {}", name, value))
+            .collect::<Vec<_>>()
+            .join("\n")
+            .parse::<toml::Value>()
+            .unwrap();
+        expected["dependencies"]
+            .as_table_mut()
+            .unwrap()
+            .insert("pallet-nicks".to_owned(), table);
+        expected["features"]["std"]
+            .as_array_mut()
+            .unwrap()
+            .push("pallet-nicks/std".into());
+        assert_eq!(parsed, expected, "{}", edited);
+
+        // Only the new dependency & feature are added
+        let mut lines = manifest.lines().peekable();
+        let added = edited
+            .lines()
+            .filter(|line| lines.next_if_eq(line).is_none())
+            .collect::<Vec<_>>();
+        assert_eq!(lines.next(), None, "{}", edited);
+        added.join("\n")
+    }
+
+    #[test]
+    fn node_template_runtime_round_trips() {
+        let manifest = include_str!("../tests/manifests/node-template-runtime.toml");
+        let added = add_pallet(
+            manifest,
+            &[
+                ("version", Value::String("2.0.0".to_owned())),
+                ("default-features", Value::Boolean(false)),
+                ("path", Value::String("../../../frame/nicks".to_owned())),
+            ],
+        );
+        assert_eq!(
+            added,
+            "pallet-nicks = { version = \"2.0.0\", default-features = false, path = \"../../../frame/nicks\" }\n\
+             \t\"pallet-nicks/std\","
+        );
+    }
+
+    #[test]
+    fn devhub_node_template_runtime_round_trips() {
+        let manifest = include_str!("../tests/manifests/devhub-node-template-runtime.toml");
+        let added = add_pallet(
+            manifest,
+            &[
+                ("version", Value::String("2.0.0-rc4".to_owned())),
+                ("default-features", Value::Boolean(false)),
+                (
+                    "git",
+                    Value::String("https://github.com/paritytech/substrate.git".to_owned()),
+                ),
+                ("tag", Value::String("v2.0.0-rc4".to_owned())),
+            ],
+        );
+        assert_eq!(
+            added,
+            "[dependencies.pallet-nicks]\n\
+             default-features = false\n\
+             git = 'https://github.com/paritytech/substrate.git'\n\
+             tag = 'v2.0.0-rc4'\n\
+             version = '2.0.0-rc4'\n\
+             \n    \
+             'pallet-nicks/std',"
+        );
+    }
+
+    #[test]
+    fn parachain_template_runtime_round_trips() {
+        let manifest = include_str!("../tests/manifests/parachain-template-runtime.toml");
+        let added = add_pallet(manifest, &[("workspace", Value::Boolean(true))]);
+        assert_eq!(
+            added,
+            "pallet-nicks = { workspace = true }\n\
+             \t\"pallet-nicks/std\","
+        );
+    }
+}
@@ -0,0 +1,195 @@
+//! Restore the files of the runtime & its node when a command fails half way, so that batch
+//! operations either apply completely or not at all.
+
+use crate::error::CliResult;
+use crate::runtime::runtime_lib_path;
+use crate::spec::runtime_declaration_path;
+use crate::workspace::{find_node_crate, find_node_file, find_workspace_root};
+
+use log::{debug, warn};
+use serde::Serialize;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Run `f`, restoring the files it may edit if it fails: the runtime manifest, `lib.rs` &
+/// `runtime.toml`, the workspace manifest, and the node manifest, `chain_spec.rs` & `rpc.rs`.
+pub fn transaction<T>(manifest_path: &Path, f: impl FnOnce() -> CliResult<T>) -> CliResult<T> {
+    let snapshot = Snapshot::take(manifest_path)?;
+    f().map_err(|e| {
+        snapshot.restore();
+        e
+    })
+}
+
+/// Content of the files of the runtime & its node which commands may edit, at some point, or
+/// `None` for the files which didn't exist then.
+#[derive(Default)]
+pub struct Snapshot(Vec<(PathBuf, Option<Vec<u8>>)>);
+
+/// Change of a file since a snapshot.
+#[derive(Clone, Debug, Serialize)]
+pub struct FileChange {
+    pub path: PathBuf,
+    /// Blocks of consecutive lines added to the file.
+    pub snippets: Vec<String>,
+}
+
+impl Snapshot {
+    /// Read the files the commands may edit for the runtime whose manifest is `manifest_path`.
+    pub fn take(manifest_path: &Path) -> CliResult<Self> {
+        let mut snapshot = Self::default();
+        snapshot.add_runtime(manifest_path)?;
+        Ok(snapshot)
+    }
+
+    /// Also read the files the commands may edit for the runtime whose manifest is
+    /// `manifest_path`, e.g. once a command created it.
+    pub fn add_runtime(&mut self, manifest_path: &Path) -> CliResult<()> {
+        for path in edited_files(manifest_path)? {
+            self.add_file(&path)?;
+        }
+        Ok(())
+    }
+
+    /// Also read the file at `path`, e.g. the manifest of the workspace a new crate joins, unless
+    /// it is already part of the snapshot.
+    pub fn add_file(&mut self, path: &Path) -> CliResult<()> {
+        if self.0.iter().any(|(file, _)| file == path) {
+            return Ok(());
+        }
+        let content = match fs::read(path) {
+            Ok(content) => Some(content),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        self.0.push((path.to_path_buf(), content));
+        Ok(())
+    }
+
+    /// Record that `path` doesn't exist yet, so that restoring removes it with the directories
+    /// created for it, e.g. the directory of a new crate.
+    pub fn add_missing(&mut self, path: &Path) {
+        // The last ancestor of a relative path is the empty path, which never exists
+        let missing = path
+            .ancestors()
+            .take_while(|dir| !dir.as_os_str().is_empty() && !dir.exists())
+            .last();
+        if let Some(missing) = missing {
+            if !self.0.iter().any(|(path, _)| path == missing) {
+                self.0.push((missing.to_path_buf(), None));
+            }
+        }
+    }
+
+    /// Write back the content of the files, and remove the files which didn't exist, in the
+    /// reverse order of the snapshot.
+    pub fn restore(&self) {
+        for (path, content) in self.0.iter().rev() {
+            debug!("Restoring: {:?}", path);
+            let restored = match content {
+                Some(content) => fs::write(path, content),
+                None if path.is_dir() => fs::remove_dir_all(path),
+                None if path.exists() => fs::remove_file(path),
+                None => Ok(()),
+            };
+            if let Err(e) = restored {
+                warn!("Could not restore {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    /// List the files changed or created since the snapshot, with the lines added to them.
+    pub fn changes(&self) -> CliResult<Vec<FileChange>> {
+        let mut changes = Vec::new();
+        for (path, content) in &self.0 {
+            if !path.is_file() {
+                continue;
+            }
+            let current = fs::read(path)?;
+            if content.as_ref() != Some(&current) {
+                changes.push(FileChange {
+                    path: path.clone(),
+                    snippets: added_snippets(
+                        &String::from_utf8_lossy(content.as_deref().unwrap_or_default()),
+                        &String::from_utf8_lossy(&current),
+                    ),
+                });
+            }
+        }
+        Ok(changes)
+    }
+}
+
+/// List the files of the runtime & its node which commands may edit or create.
+fn edited_files(manifest_path: &Path) -> CliResult<Vec<PathBuf>> {
+    let mut files = vec![
+        manifest_path.to_path_buf(),
+        runtime_lib_path(manifest_path),
+        runtime_declaration_path(manifest_path),
+    ];
+    files.extend(find_workspace_root(manifest_path)?);
+    files.extend(find_node_crate(manifest_path)?.map(|dir| dir.join("Cargo.toml")));
+    files.extend(find_node_file(manifest_path, "chain_spec.rs")?);
+    files.extend(find_node_file(manifest_path, "rpc.rs")?);
+    Ok(files)
+}
+
+/// Blocks of consecutive lines of `new` which aren't in `old`, matching the lines of both with
+/// their longest common subsequence.
+fn added_snippets(old: &str, new: &str) -> Vec<String> {
+    let old = old.lines().collect::<Vec<_>>();
+    let new = new.lines().collect::<Vec<_>>();
+
+    // The lines before & after the changes are common to both
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (old, new) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+
+    // lengths[i][j] is the length of the longest common subsequence of old[i..] & new[j..]
+    let mut lengths = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if old[i] == new[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut snippets = Vec::new();
+    let mut snippet: Vec<&str> = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while j < new.len() {
+        if i < old.len() && old[i] == new[j] {
+            i += 1;
+            j += 1;
+        } else if i < old.len() && lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+            continue;
+        } else {
+            snippet.push(new[j]);
+            j += 1;
+            continue;
+        }
+        if !snippet.is_empty() {
+            snippets.push(snippet.join("\n"));
+            snippet.clear();
+        }
+    }
+    if !snippet.is_empty() {
+        snippets.push(snippet.join("\n"));
+    }
+
+    snippets
+}
@@ -0,0 +1,289 @@
+use crate::error::{CliError, CliResult};
+use crate::manifest::{edit_manifest_text, relative_path};
+use crate::metadata::Manifest;
+use crate::output::message;
+use crate::runtime::{check_runtime_declaration, runtime_lib_path};
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use log::debug;
+
+/// A workspace member crate that declares a Substrate runtime.
+#[derive(Clone, Debug)]
+pub struct RuntimeCandidate {
+    pub name: String,
+    pub manifest_path: PathBuf,
+}
+
+/// Resolve the manifest of the runtime crate to operate on.
+///
+/// If `manifest_path` points to a package manifest, it is returned as is. If it points to a
+/// workspace manifest, the workspace members declaring a runtime with `construct_runtime!` or a
+/// `#[frame_support::runtime]` module are considered, and the one named `runtime` is selected (or
+/// the only one, if there is a single runtime in the workspace). Selecting a `runtime` other than
+/// the package is an error.
+pub fn find_runtime_manifest(manifest_path: &Path, runtime: Option<&str>) -> CliResult<PathBuf> {
+    let manifest = read_manifest(manifest_path)?;
+
+    if manifest.workspace().is_none() {
+        // Only the runtime of a workspace can be selected, unless it names the crate itself
+        let name = manifest.package().as_ref().map(|package| package.name());
+        return match runtime {
+            Some(runtime) if name != Some(runtime) => Err(CliError::Manifest(format!(
+                "Could not select runtime '{}' with --runtime, {:?} is not a workspace manifest.",
+                runtime, manifest_path
+            ))),
+            _ => Ok(manifest_path.to_path_buf()),
+        };
+    }
+    if runtime.is_none() && manifest.package().is_some() && is_runtime(manifest_path)? {
+        return Ok(manifest_path.to_path_buf());
+    }
+
+    let candidates = runtime_candidates(manifest_path, &manifest)?;
+    debug!(
+        "Runtime candidates: {:?}",
+        candidates.iter().map(|c| &c.name).collect::<Vec<_>>()
+    );
+
+    let selected = match runtime {
+        Some(runtime) => candidates.iter().find(|c| c.name == runtime),
+        None if candidates.len() == 1 => candidates.first(),
+        None => None,
+    };
+
+    match selected {
+        Some(candidate) => Ok(candidate.manifest_path.clone()),
+        None if candidates.is_empty() => Err(CliError::Manifest(format!(
+            "Could not find any runtime crate in workspace {:?}.",
+            manifest_path
+        ))),
+        None => Err(CliError::Manifest(format!(
+            "{} in workspace {:?}, please select one with --runtime <name>: {}",
+            match runtime {
+                Some(runtime) => format!("Could not find runtime '{}'", runtime),
+                None => "Found several runtimes".to_owned(),
+            },
+            manifest_path,
+            candidates
+                .iter()
+                .map(|c| c.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))),
+    }
+}
+
+/// List the members of the workspace declared in `manifest_path` whose library declares a
+/// Substrate runtime with `construct_runtime!` or a `#[frame_support::runtime]` module.
+pub fn runtime_candidates(
+    manifest_path: &Path,
+    manifest: &Manifest,
+) -> CliResult<Vec<RuntimeCandidate>> {
+    let mut candidates = Vec::new();
+
+    for member_path in workspace_members(manifest_path, manifest)? {
+        let member = read_manifest(&member_path)?;
+        let package = match member.package() {
+            Some(package) => package,
+            None => continue,
+        };
+
+        if is_runtime(&member_path)? {
+            candidates.push(RuntimeCandidate {
+                name: package.name().to_owned(),
+                manifest_path: member_path,
+            });
+        }
+    }
+
+    Ok(candidates)
+}
+
+/// List the manifest paths of the workspace members, expanding the glob patterns of their paths
+/// like cargo does.
+pub fn workspace_members(manifest_path: &Path, manifest: &Manifest) -> CliResult<Vec<PathBuf>> {
+    let root = manifest_path.parent().unwrap_or_else(|| Path::new("."));
+    let workspace = match manifest.workspace() {
+        Some(workspace) => workspace,
+        None => return Ok(Vec::new()),
+    };
+
+    let mut members = Vec::new();
+    for member in workspace.members() {
+        let pattern = Path::new(&glob::Pattern::escape(&root.to_string_lossy()))
+            .join(member.trim_end_matches('/'));
+        let dirs = glob::glob(&pattern.to_string_lossy()).map_err(|e| {
+            CliError::Manifest(format!("Invalid workspace member {}: {}", member, e))
+        })?;
+
+        for dir in dirs {
+            let dir = dir.map_err(io::Error::from)?;
+            let path = dir.join("Cargo.toml");
+            if !workspace.excludes(root, &dir) && path.is_file() && !members.contains(&path) {
+                members.push(path);
+            }
+        }
+    }
+
+    Ok(members)
+}
+
+/// Find the manifest of the workspace the crate whose manifest is `manifest_path` belongs to, i.e.
+/// the workspace enclosing it if its members include the crate.
+pub fn find_workspace_root(manifest_path: &Path) -> CliResult<Option<PathBuf>> {
+    let root_path = match find_enclosing_workspace_root(manifest_path)? {
+        Some(root_path) => root_path,
+        None => return Ok(None),
+    };
+    let manifest_path = manifest_path.canonicalize()?;
+    let is_member = workspace_members(&root_path, &read_manifest(&root_path)?)?
+        .iter()
+        .any(|member| member.canonicalize().ok().as_ref() == Some(&manifest_path));
+    if !is_member {
+        debug!("Not a member of workspace {:?}", root_path);
+        return Ok(None);
+    }
+
+    Ok(Some(root_path))
+}
+
+/// Find the manifest of the workspace enclosing the crate whose manifest is `manifest_path`, by
+/// searching its parent directories for a manifest with a `[workspace]` table, unless the
+/// workspace excludes the crate. The crate may not be a member of the workspace yet.
+pub fn find_enclosing_workspace_root(manifest_path: &Path) -> CliResult<Option<PathBuf>> {
+    let manifest_path = manifest_path.canonicalize()?;
+    let crate_dir = manifest_path.parent().unwrap_or_else(|| Path::new("/"));
+    for dir in manifest_path.ancestors().skip(2) {
+        let root_path = dir.join("Cargo.toml");
+        if !root_path.is_file() {
+            continue;
+        }
+        // Like cargo, the first workspace found is the only candidate
+        if let Some(workspace) = read_manifest(&root_path)?.workspace() {
+            return Ok(Some(root_path).filter(|_| !workspace.excludes(dir, crate_dir)));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Add the crate in `dir` to the members of the workspace whose manifest is `root_path`, unless
+/// one of its members already matches it e.g. `pallets/*`.
+pub fn add_workspace_member(root_path: &Path, dir: &Path) -> CliResult<()> {
+    let manifest = read_manifest(root_path)?;
+    let crate_manifest_path = dir.join("Cargo.toml").canonicalize()?;
+    let members = workspace_members(root_path, &manifest)?;
+    if members
+        .iter()
+        .any(|member| member.canonicalize().ok().as_ref() == Some(&crate_manifest_path))
+    {
+        return Ok(());
+    }
+
+    let root_dir = root_path
+        .canonicalize()?
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    let member = relative_path(&root_dir, &dir.canonicalize()?)
+        .to_string_lossy()
+        .replace('\\', "/");
+    edit_manifest_text(root_path, |text| {
+        text.insert_into_array(&["workspace", "members"], &member);
+        Ok(())
+    })?;
+    message(format!("Added {} to your workspace members.", member));
+
+    Ok(())
+}
+
+/// Find the directory of the node crate of the runtime whose manifest is `manifest_path`, i.e.
+/// the crate with a `chain_spec.rs` among the other members of its workspace, or its siblings.
+pub fn find_node_crate(manifest_path: &Path) -> CliResult<Option<PathBuf>> {
+    let manifest_path = manifest_path.canonicalize()?;
+    let runtime_dir = manifest_path.parent().unwrap_or_else(|| Path::new("."));
+
+    let crate_dirs = match find_workspace_root(&manifest_path)? {
+        Some(root_path) => workspace_members(&root_path, &read_manifest(&root_path)?)?
+            .iter()
+            .filter_map(|member_path| member_path.parent().map(Path::to_path_buf))
+            .collect::<Vec<_>>(),
+        None => match runtime_dir.parent() {
+            Some(parent) => fs::read_dir(parent)?
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .collect(),
+            None => Vec::new(),
+        },
+    };
+
+    for dir in crate_dirs {
+        let dir = dir.canonicalize()?;
+        if dir == runtime_dir || !dir.join("Cargo.toml").is_file() {
+            continue;
+        }
+        if find_file(&dir.join("src"), "chain_spec.rs")?.is_some() {
+            return Ok(Some(dir));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Find the source file named `file_name` of the node crate of the runtime whose manifest is
+/// `manifest_path` e.g. `chain_spec.rs` or `rpc.rs`.
+pub fn find_node_file(manifest_path: &Path, file_name: &str) -> CliResult<Option<PathBuf>> {
+    match find_node_crate(manifest_path)? {
+        Some(node_dir) => find_file(&node_dir.join("src"), file_name),
+        None => Ok(None),
+    }
+}
+
+/// Search `dir` recursively for a file named `file_name`.
+fn find_file(dir: &Path, file_name: &str) -> CliResult<Option<PathBuf>> {
+    if !dir.is_dir() {
+        return Ok(None);
+    }
+
+    let mut entries = fs::read_dir(dir)?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .collect::<Vec<_>>();
+    entries.sort();
+
+    for path in entries {
+        if path.is_dir() {
+            if let Some(found) = find_file(&path, file_name)? {
+                return Ok(Some(found));
+            }
+        } else if path.file_name().and_then(|name| name.to_str()) == Some(file_name) {
+            return Ok(Some(path));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Whether the library of the crate whose manifest is `manifest_path` declares a runtime.
+fn is_runtime(manifest_path: &Path) -> CliResult<bool> {
+    let lib_path = runtime_lib_path(manifest_path);
+    if !lib_path.is_file() {
+        return Ok(false);
+    }
+    let runtime = fs::read_to_string(&lib_path)?;
+    Ok(check_runtime_declaration(&lib_path, &runtime).is_ok())
+}
+
+pub fn read_manifest(manifest_path: &Path) -> CliResult<Manifest> {
+    let s = fs::read_to_string(manifest_path)?;
+    toml::from_str(&s).map_err(|e| {
+        CliError::Manifest(format!(
+            "Could not parse manifest {:?} as TOML: {}",
+            manifest_path, e
+        ))
+    })
+}
@@ -0,0 +1,784 @@
+//! Feed bad manifest paths, manifests & runtimes to the subcommands, which must report an error
+//! with the exit code of its category rather than panic.
+
+use std::{
+    env, fs,
+    path::PathBuf,
+    process::{self, Command, Output},
+};
+use substrate_deps::{
+    error::CliError,
+    graph::{execute_graph, GraphOptions},
+};
+
+const WORKSPACE_MANIFEST: &str = r#"[workspace]
+members = ["runtime"]
+"#;
+
+const RUNTIME_MANIFEST: &str = r#"[package]
+name = "node-runtime"
+version = "2.0.0"
+edition = "2018"
+
+[dependencies]
+codec = { package = "parity-scale-codec", version = "1.3.4", default-features = false }
+frame-support = { version = "2.0.0", default-features = false }
+pallet-sudo = { version = "2.0.0", default-features = false }
+system = { package = "frame-system", version = "2.0.0", default-features = false }
+
+[features]
+default = ["std"]
+std = [
+    "codec/std",
+    "frame-support/std",
+    "pallet-sudo/std",
+    "system/std",
+]
+"#;
+
+const RUNTIME_LIB: &str = r#"#![cfg_attr(not(feature = "std"), no_std)]
+
+impl system::Trait for Runtime {
+    type AccountId = AccountId;
+}
+
+impl pallet_sudo::Trait for Runtime {
+    type Event = Event;
+    type Call = Call;
+}
+
+construct_runtime!(
+    pub enum Runtime where
+        Block = Block,
+        NodeBlock = opaque::Block,
+        UncheckedExtrinsic = UncheckedExtrinsic
+    {
+        System: system::{Module, Call, Config, Storage, Event<T>},
+        Sudo: pallet_sudo::{Module, Call, Config<T>, Storage, Event<T>},
+    }
+);
+"#;
+
+/// Subcommands run against the runtime, with arguments which would be valid for it.
+const SUBCOMMANDS: [&[&str]; 9] = [
+    &["add", "pallet-nicks"],
+    &["remove", "pallet-sudo"],
+    &["features", "pallet-sudo"],
+    &["graph"],
+    &["check"],
+    &["new-pallet", "pallet-kitties"],
+    &["sync"],
+    &["export"],
+    &["diff", "HEAD"],
+];
+
+/// A workspace with a runtime crate, in a temporary directory removed on drop.
+struct Fixture {
+    dir: PathBuf,
+}
+
+impl Fixture {
+    fn new(name: &str) -> Self {
+        let fixture = Self::empty(name);
+        fixture.write("Cargo.toml", WORKSPACE_MANIFEST);
+        fixture.write("runtime/Cargo.toml", RUNTIME_MANIFEST);
+        fixture.write("runtime/src/lib.rs", RUNTIME_LIB);
+        fixture
+    }
+
+    fn empty(name: &str) -> Self {
+        let dir = env::temp_dir().join(format!("substrate-deps-test-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        Self { dir }
+    }
+
+    fn path(&self, path: &str) -> PathBuf {
+        self.dir.join(path)
+    }
+
+    fn write(&self, path: &str, content: &str) {
+        let path = self.path(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    fn read(&self, path: &str) -> String {
+        fs::read_to_string(self.path(path)).unwrap()
+    }
+
+    /// Write the pallet crate `pallet-local` in `pallets/local`, with a `std` feature.
+    fn local_pallet(&self) {
+        self.local_pallet_with("\n[features]\nstd = []\n");
+    }
+
+    /// Write the pallet crate `pallet-local` in `pallets/local`, with the `sections` following its
+    /// package in its manifest.
+    fn local_pallet_with(&self, sections: &str) {
+        self.write(
+            "pallets/local/Cargo.toml",
+            &format!(
+                "[package]\nname = \"pallet-local\"\nversion = \"0.1.0\"\n{}",
+                sections
+            ),
+        );
+        self.write("pallets/local/src/lib.rs", "");
+    }
+
+    /// Command running `substrate-deps` in the fixture directory.
+    fn command(&self, args: &[&str]) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_substrate-deps"));
+        command.args(args).current_dir(&self.dir);
+        command
+    }
+
+    /// Run `substrate-deps` in the fixture directory.
+    fn run(&self, args: &[&str]) -> Output {
+        self.command(args).output().unwrap()
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Assert that `output` reports a success, printing its errors otherwise.
+fn assert_success(output: &Output) {
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+/// Assert that `output` reports an error with the exit `code`, without panicking.
+fn assert_error(output: &Output, code: i32, args: &[&str]) {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        !stderr.contains("panicked"),
+        "{:?} panicked: {}",
+        args,
+        stderr
+    );
+    assert!(
+        stderr.contains("error: "),
+        "{:?} reported no error: {}",
+        args,
+        stderr
+    );
+    assert_eq!(output.status.code(), Some(code), "{:?}: {}", args, stderr);
+}
+
+#[test]
+fn missing_manifest_is_reported() {
+    let fixture = Fixture::empty("missing-manifest");
+    // Outside of the fixture, whose parent directories could hold a manifest
+    for args in &SUBCOMMANDS {
+        let args = [
+            args,
+            &["--manifest-path", "/substrate-deps-missing/Cargo.toml"][..],
+        ]
+        .concat();
+        assert_error(&fixture.run(&args), 2, &args);
+    }
+}
+
+#[test]
+fn manifest_path_without_file_name_is_reported() {
+    let fixture = Fixture::new("no-file-name");
+    for args in &SUBCOMMANDS {
+        let args = [args, &["--manifest-path", "missing/.."][..]].concat();
+        assert_error(&fixture.run(&args), 2, &args);
+    }
+}
+
+#[test]
+fn directory_manifest_path_is_the_manifest_it_contains() {
+    let fixture = Fixture::new("directory");
+    let output = fixture.run(&["export", "--manifest-path", "runtime"]);
+    assert_success(&output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("[pallets.sudo]"));
+}
+
+#[test]
+fn manifest_which_is_not_toml_is_reported() {
+    let fixture = Fixture::new("not-toml");
+    for args in &SUBCOMMANDS {
+        let args = [args, &["--manifest-path", "runtime/src/lib.rs"][..]].concat();
+        assert_error(&fixture.run(&args), 2, &args);
+    }
+}
+
+#[test]
+fn workspace_without_runtime_is_reported() {
+    let fixture = Fixture::new("no-runtime");
+    fixture.write("runtime/src/lib.rs", "pub fn noop() {}\n");
+    for args in &SUBCOMMANDS {
+        assert_error(&fixture.run(args), 2, args);
+    }
+}
+
+#[test]
+fn runtime_of_package_manifest_is_not_selectable() {
+    let fixture = Fixture::new("runtime-of-package");
+    let args = [
+        "export",
+        "--runtime",
+        "other-runtime",
+        "--manifest-path",
+        "runtime/Cargo.toml",
+    ];
+    assert_error(&fixture.run(&args), 2, &args);
+
+    let args = [
+        "export",
+        "--runtime",
+        "node-runtime",
+        "--manifest-path",
+        "runtime/Cargo.toml",
+    ];
+    let output = fixture.run(&args);
+    assert_success(&output);
+}
+
+#[test]
+fn workspace_member_globs_are_expanded() {
+    let fixture = Fixture::empty("member-globs");
+    fixture.write(
+        "Cargo.toml",
+        "[workspace]\nmembers = [\"bin/**/node\", \"runtimes/[!.]?v*-[a-c]\"]\nexclude = [\"runtimes/dev-b\"]\n",
+    );
+    for dir in &["runtimes/dev-a", "runtimes/dev-b", "runtimes/dev-d"] {
+        fixture.write(
+            &format!("{}/Cargo.toml", dir),
+            &RUNTIME_MANIFEST.replace("node-runtime", dir.trim_start_matches("runtimes/")),
+        );
+        fixture.write(&format!("{}/src/lib.rs", dir), RUNTIME_LIB);
+    }
+
+    // dev-b is excluded & dev-d doesn't match, leaving dev-a the only runtime
+    let output = fixture.run(&["export"]);
+    assert_success(&output);
+    let args = ["export", "--runtime", "dev-d"];
+    let output = fixture.run(&args);
+    assert_error(&output, 2, &args);
+    assert!(String::from_utf8_lossy(&output.stderr).contains(": dev-a"));
+}
+
+#[test]
+fn runtime_module_is_found_in_workspace() {
+    let fixture = Fixture::new("runtime-module");
+    fixture.write(
+        "runtime/src/lib.rs",
+        "#[frame_support::runtime]\nmod runtime {\n    #[runtime::pallet_index(0)]\n    pub type System = system;\n}\n",
+    );
+    let output = fixture.run(&["export"]);
+    assert_success(&output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("index = 0"));
+}
+
+#[test]
+fn runtime_without_construct_runtime_is_reported() {
+    let fixture = Fixture::new("no-construct-runtime");
+    fixture.write("runtime/src/lib.rs", "pub fn noop() {}\n");
+    let subcommands: [&[&str]; 3] = [
+        &["remove", "pallet-sudo"],
+        &["export"],
+        &["add", "pallet-local", "--path", "pallets/local"],
+    ];
+    fixture.local_pallet_with("");
+    for args in &subcommands {
+        let args = [args, &["--manifest-path", "runtime/Cargo.toml"][..]].concat();
+        let output = fixture.run(&args);
+        assert_error(&output, 3, &args);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("hint: "));
+    }
+}
+
+#[test]
+fn graph_of_virtual_manifest_is_an_error() {
+    let fixture = Fixture::new("graph-virtual");
+    let result = execute_graph(&fixture.path("Cargo.toml"), &GraphOptions::default());
+    match result {
+        Err(CliError::Manifest(_)) => {}
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn local_pallet_without_version_is_a_path_dependency() {
+    let fixture = Fixture::new("path-only");
+    fixture.write(
+        "pallets/local/Cargo.toml",
+        "[package]\nname = \"pallet-local\"\nversion.workspace = true\n\n[features]\nstd = []\n",
+    );
+    fixture.write("pallets/local/src/lib.rs", "");
+
+    let output = fixture.run(&["add", "pallet-local", "--path", "pallets/local"]);
+    assert_success(&output);
+    assert!(fixture
+        .read("runtime/Cargo.toml")
+        .contains(r#"pallet_local = { package = "pallet-local", path = "../pallets/local", default-features = false }"#));
+    assert!(fixture
+        .read("runtime/src/lib.rs")
+        .contains("PalletLocal: pallet_local"));
+}
+
+#[test]
+fn workspace_dependencies_are_used_by_members_inheriting_them() {
+    for (members, inherited) in &[(r#"["runtime"]"#, true), (r#"["node"]"#, false)] {
+        let fixture = Fixture::new("workspace-dependencies");
+        fixture.write(
+            "Cargo.toml",
+            &format!(
+                "[workspace]\nmembers = {}\n\n[workspace.dependencies]\ncodec = {{ package = \"parity-scale-codec\", version = \"1.3.4\" }}\n",
+                members
+            ),
+        );
+        fixture.write(
+            "runtime/Cargo.toml",
+            &RUNTIME_MANIFEST.replace(
+                r#"codec = { package = "parity-scale-codec", version = "1.3.4", default-features = false }"#,
+                "codec = { workspace = true, default-features = false }",
+            ),
+        );
+        fixture.local_pallet();
+
+        let args = [
+            "add",
+            "pallet-local",
+            "--path",
+            "pallets/local",
+            "--manifest-path",
+            "runtime",
+        ];
+        let output = fixture.run(&args);
+        assert_success(&output);
+        assert_eq!(
+            fixture.read("Cargo.toml").contains("pallet_local"),
+            *inherited,
+            "{}",
+            members
+        );
+        assert_eq!(
+            fixture
+                .read("runtime/Cargo.toml")
+                .contains("pallet_local = { workspace = true"),
+            *inherited,
+            "{}",
+            members
+        );
+    }
+}
+
+#[test]
+fn construct_runtime_parts_are_listed() {
+    let metadata = [
+        (
+            "[package.metadata.substrate.genesis-config]\nvalue = \"0\"\n",
+            "PalletLocal: pallet_local::{Module, Call, Storage, Event<T>, Config<T>} = 2,",
+        ),
+        (
+            "[package.metadata.substrate]\nparts = [\"Module\", \"Call\", \"Inherent\"]\n",
+            "PalletLocal: pallet_local::{Module, Call, Inherent} = 2,",
+        ),
+    ];
+    for (metadata, declaration) in &metadata {
+        let fixture = Fixture::new("construct-runtime-parts");
+        fixture.local_pallet_with(&format!("\n{}\n[features]\nstd = []\n", metadata));
+
+        let output = fixture.run(&["add", "pallet-local", "--path", "pallets/local"]);
+        assert_success(&output);
+        let lib = fixture.read("runtime/src/lib.rs");
+        assert!(lib.contains(declaration), "{}", lib);
+    }
+}
+
+#[test]
+fn invalid_arguments_are_reported() {
+    let fixture = Fixture::new("invalid-arguments");
+    let invalid: [&[&str]; 5] = [
+        &["add", "pallet-nicks", "--instance", "one"],
+        &["add", "pallet-nicks", "pallet-sudo", "--alias", "nicks"],
+        &["remove", "pallet-sudo", "--instance", "-1"],
+        &["new-pallet", "1pallet"],
+        &["init-runtime", "--path", "runtime"],
+    ];
+    for args in &invalid {
+        assert_error(&fixture.run(args), 1, args);
+    }
+}
+
+#[test]
+fn errors_are_printed_as_json() {
+    let fixture = Fixture::new("json");
+    fixture.write("runtime/src/lib.rs", "pub fn noop() {}\n");
+    let output = fixture.run(&[
+        "export",
+        "--manifest-path",
+        "runtime/Cargo.toml",
+        "--message-format",
+        "json",
+    ]);
+    assert_eq!(output.status.code(), Some(3));
+    let error: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(error["reason"], "error");
+    assert_eq!(error["kind"], "construct_runtime_not_found");
+    assert_eq!(error["code"], 3);
+    assert!(error["details"]["file"]
+        .as_str()
+        .unwrap()
+        .ends_with("lib.rs"));
+}
+
+#[test]
+fn added_pallets_are_reported_as_json() {
+    let fixture = Fixture::new("add-json");
+    fixture.local_pallet();
+
+    let output = fixture.run(&[
+        "add",
+        "pallet-local",
+        "--path",
+        "pallets/local",
+        "--message-format",
+        "json",
+    ]);
+    assert_success(&output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains("Added pallet"), "{}", stderr);
+    let added: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(added["reason"], "pallet-added");
+    assert_eq!(added["name"], "pallet-local");
+    assert_eq!(added["version"], "0.1.0");
+    assert_eq!(added["registry"], serde_json::Value::Null);
+
+    let files = added["files"].as_array().unwrap();
+    let lib = files
+        .iter()
+        .find(|file| file["path"].as_str().unwrap().ends_with("lib.rs"))
+        .unwrap();
+    assert!(lib["snippets"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|snippet| snippet
+            .as_str()
+            .unwrap()
+            .contains("PalletLocal: pallet_local")));
+}
+
+#[test]
+fn quiet_runs_print_nothing_but_errors() {
+    let fixture = Fixture::new("quiet");
+    fixture.local_pallet();
+
+    let output = fixture
+        .command(&["add", "pallet-local", "--path", "pallets/local", "-q"])
+        .env("RUST_LOG", "trace")
+        .output()
+        .unwrap();
+    assert_success(&output);
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+
+    // The manifest is searched in the parent directories without a word
+    let output = fixture
+        .command(&["export", "-q"])
+        .current_dir(fixture.path("runtime/src"))
+        .output()
+        .unwrap();
+    assert_success(&output);
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+
+    let output = fixture.run(&["remove", "pallet-missing", "-q"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("error: "));
+}
+
+#[test]
+fn added_pallets_are_reported_in_order() {
+    let fixture = Fixture::new("add-messages");
+    fixture.local_pallet();
+
+    let output = fixture.run(&["add", "pallet-local", "--path", "pallets/local"]);
+    assert_success(&output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let lines = stderr.lines().collect::<Vec<_>>();
+    let position = |line: &str| lines.iter().position(|l| l.trim_start() == line);
+    let adding = position("Adding pallet-local v0.1.0").unwrap();
+    let runtime =
+        position("Added pallet pallet-local v0.1.0 configuration in your node runtime.").unwrap();
+    let manifest =
+        position("Added pallet pallet-local v0.1.0 as dependency in your node runtime manifest.")
+            .unwrap();
+    assert!(adding < runtime && runtime < manifest, "{}", stderr);
+}
+
+#[test]
+fn missing_std_feature_is_created() {
+    let fixture = Fixture::new("no-std-feature");
+    fixture.write(
+        "runtime/Cargo.toml",
+        &RUNTIME_MANIFEST.replace("[features]\ndefault = [\"std\"]\nstd = [\n    \"codec/std\",\n    \"frame-support/std\",\n    \"pallet-sudo/std\",\n    \"system/std\",\n]\n", "[features]\ndefault = [\"std\"]\n"),
+    );
+    fixture.local_pallet();
+
+    let output = fixture.run(&["add", "pallet-local", "--path", "pallets/local"]);
+    assert_success(&output);
+    assert!(fixture
+        .read("runtime/Cargo.toml")
+        .contains(r#"std = ["pallet_local/std"]"#));
+}
+
+#[test]
+fn std_feature_is_only_forwarded_to_pallets_having_one() {
+    let fixture = Fixture::new("pallet-without-std");
+    fixture.local_pallet_with("");
+
+    let output = fixture.run(&["add", "pallet-local", "--path", "pallets/local"]);
+    assert_success(&output);
+    let manifest = fixture.read("runtime/Cargo.toml");
+    assert!(
+        manifest.contains("package = \"pallet-local\""),
+        "{}",
+        manifest
+    );
+    assert!(!manifest.contains("pallet_local/std"), "{}", manifest);
+}
+
+#[test]
+fn command_results_are_printed_as_json() {
+    let fixture = Fixture::new("results-json");
+    let json = |args: &[&str]| -> Vec<serde_json::Value> {
+        let args = [args, &["--message-format", "json"][..]].concat();
+        let output = fixture.run(&args);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            output.status.success(),
+            "{:?}: {}{}",
+            args,
+            stdout,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        stdout
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap_or_else(|e| panic!("{}: {}", e, line)))
+            .collect()
+    };
+
+    let features = json(&["features", "pallet-sudo"]);
+    assert_eq!(features[0]["reason"], "pallet-features");
+    assert_eq!(features[0]["key"], "pallet-sudo");
+
+    // The runtime before removing the pallet
+    fixture.write("old/Cargo.toml", RUNTIME_MANIFEST);
+    fixture.write("old/src/lib.rs", RUNTIME_LIB);
+
+    let removed = json(&[
+        "remove",
+        "pallet-sudo",
+        "--alias",
+        "sudo",
+        "--manifest-path",
+        "runtime",
+    ]);
+    assert_eq!(removed[0]["reason"], "pallet-removed");
+    assert_eq!(removed[0]["name"], "Sudo");
+    assert_eq!(removed[0]["dependency_removed"], true);
+
+    let changes = json(&["diff", "old", "--manifest-path", "runtime"]);
+    assert_eq!(changes[0]["reason"], "pallet-change");
+    assert_eq!(changes[0]["change"], "removed");
+    assert_eq!(changes[0]["key"], "sudo");
+}
+
+#[test]
+fn pallet_is_removed_by_its_crate_without_alias() {
+    let fixture = Fixture::new("remove-without-alias");
+    let output = fixture.run(&["remove", "pallet-sudo", "--manifest-path", "runtime"]);
+    assert_success(&output);
+    let lib = fixture.read("runtime/src/lib.rs");
+    assert!(!lib.contains("pallet_sudo"), "{}", lib);
+    assert!(!fixture.read("runtime/Cargo.toml").contains("pallet-sudo"));
+}
+
+#[test]
+fn removed_declaration_is_only_the_construct_runtime_entry() {
+    let fixture = Fixture::new("remove-entry");
+    let lib = RUNTIME_LIB
+        .replace(
+            "construct_runtime!(",
+            "pub struct Keys {\n    Sudo: u32,\n}\n\nconstruct_runtime!(",
+        )
+        .replace(
+            "Sudo: pallet_sudo::{Module, Call, Config<T>, Storage, Event<T>},",
+            "Sudo: pallet_sudo::{\n            Module, Call, Config<T>,\n            Storage, Event<T>,\n        },\n        Aura: aura::{Module, Config<T>},",
+        );
+    fixture.write("runtime/src/lib.rs", &lib);
+
+    let output = fixture.run(&[
+        "remove",
+        "pallet-sudo",
+        "--alias",
+        "sudo",
+        "--manifest-path",
+        "runtime",
+    ]);
+    assert_success(&output);
+    let lib = fixture.read("runtime/src/lib.rs");
+    assert!(
+        lib.contains("pub struct Keys {\n    Sudo: u32,\n}"),
+        "{}",
+        lib
+    );
+    assert!(lib.contains(
+        "System: system::{Module, Call, Config, Storage, Event<T>},\n        Aura: aura::{Module, Config<T>},\n    }"
+    ), "{}", lib);
+    assert!(!lib.contains("pallet_sudo"), "{}", lib);
+}
+
+#[test]
+fn failed_new_pallet_leaves_no_files() {
+    let fixture = Fixture::new("new-pallet-rollback");
+    fixture.write("runtime/src/lib.rs", "pub fn noop() {}\n");
+    let args = [
+        "new-pallet",
+        "pallet-kitties",
+        "--add",
+        "--manifest-path",
+        "runtime/Cargo.toml",
+    ];
+    let output = fixture.run(&args);
+    assert_error(&output, 3, &args);
+    assert!(!fixture.path("pallets").exists());
+    assert_eq!(fixture.read("Cargo.toml"), WORKSPACE_MANIFEST);
+    assert_eq!(fixture.read("runtime/Cargo.toml"), RUNTIME_MANIFEST);
+}
+
+#[test]
+fn new_pallet_of_frame_v2_runtime_is_rejected() {
+    let fixture = Fixture::new("new-pallet-frame-v2");
+    fixture.write(
+        "runtime/src/lib.rs",
+        &(RUNTIME_LIB.to_owned() + "\nimpl pallet_sudo::Config for Runtime {}\n"),
+    );
+    let args = ["new-pallet", "pallet-kitties", "--manifest-path", "runtime"];
+    let output = fixture.run(&args);
+    assert_error(&output, 3, &args);
+    assert!(!fixture.path("pallets").exists());
+}
+
+#[test]
+fn runtime_api_of_local_pallet_is_removed() {
+    let fixture = Fixture::new("remove-local-api");
+    fixture
+        .local_pallet_with("\n[package.metadata.substrate.runtime-api]\nimplementation = \"\"\n");
+    fixture.write(
+        "runtime/Cargo.toml",
+        &RUNTIME_MANIFEST.replace(
+            "[features]",
+            "pallet-local = { path = \"../pallets/local\", default-features = false }\npallet-local-rpc-runtime-api = { path = \"../pallets/local/api\", default-features = false }\n\n[features]",
+        ),
+    );
+    fixture.write(
+        "runtime/src/lib.rs",
+        &(RUNTIME_LIB.replace(
+            "Sudo: pallet_sudo::{Module, Call, Config<T>, Storage, Event<T>},",
+            "Sudo: pallet_sudo::{Module, Call, Config<T>, Storage, Event<T>},\n        PalletLocal: pallet_local::{Module, Call},",
+        ) + "\nimpl_runtime_apis! {\n    impl pallet_local_rpc_runtime_api::LocalApi<Block> for Runtime {\n        fn value() -> u32 {\n            PalletLocal::value()\n        }\n    }\n}\n"),
+    );
+
+    let output = fixture.run(&["remove", "pallet-local", "--manifest-path", "runtime"]);
+    assert_success(&output);
+    let lib = fixture.read("runtime/src/lib.rs");
+    assert!(!lib.contains("pallet_local_rpc_runtime_api"), "{}", lib);
+    assert!(!fixture
+        .read("runtime/Cargo.toml")
+        .contains("pallet-local-rpc-runtime-api"));
+}
+
+#[test]
+fn runtime_api_is_only_added_with_rpc() {
+    let fixture = Fixture::new("runtime-api-with-rpc");
+    fixture.local_pallet_with(
+        "\n[package.metadata.substrate.runtime-api]\nimplementation = \"impl {runtime_api}::LocalApi<Block> for Runtime {}\"\n",
+    );
+
+    // The runtime doesn't have any impl_runtime_apis! to implement the API in
+    let output = fixture.run(&["add", "pallet-local", "--path", "pallets/local"]);
+    assert_success(&output);
+    assert!(fixture
+        .read("runtime/src/lib.rs")
+        .contains("PalletLocal: pallet_local"));
+    assert!(!fixture
+        .read("runtime/Cargo.toml")
+        .contains("pallet-local-rpc-runtime-api"));
+}
+
+#[test]
+fn new_pallet_takes_a_registry() {
+    let fixture = Fixture::new("new-pallet-registry");
+    // The index of a local pallet isn't read, the registry only needs to be declared
+    fixture.write(
+        ".cargo/config",
+        "[registries.local]\nindex = \"file:///nonexistent/index\"\n",
+    );
+    let output = fixture.run(&[
+        "new-pallet",
+        "pallet-kitties",
+        "--add",
+        "--registry",
+        "local",
+        "--manifest-path",
+        "runtime",
+    ]);
+    assert_success(&output);
+    let manifest = fixture.read("runtime/Cargo.toml");
+    let dependency = manifest
+        .lines()
+        .find(|line| line.contains("package = \"pallet-kitties\""))
+        .unwrap_or_else(|| panic!("{}", manifest));
+    assert!(dependency.contains("registry = \"local\""), "{}", manifest);
+}
+
+#[test]
+fn init_runtime_takes_a_registry() {
+    let fixture = Fixture::empty("init-runtime-registry");
+    let output = fixture.run(&["init-runtime", "--registry", "crates-io"]);
+    assert_success(&output);
+    assert!(fixture.path("runtime/Cargo.toml").exists());
+}
+
+#[test]
+fn unsupported_frame_version_is_reported() {
+    let fixture = Fixture::empty("init-runtime-frame-version");
+    let args = ["init-runtime", "--frame-version", "3.0.0"];
+    let output = fixture.run(&args);
+    assert_error(&output, 1, &args);
+    assert!(!fixture.path("runtime").exists());
+}
+
+#[test]
+fn failed_init_runtime_restores_the_workspace() {
+    let fixture = Fixture::empty("init-runtime-rollback");
+    let workspace_manifest = "[workspace]\nmembers = []\n";
+    fixture.write("Cargo.toml", workspace_manifest);
+    fixture.write(
+        "pallets.toml",
+        "[pallets.pallet-local]\npath = \"pallets/missing\"\n",
+    );
+    let args = [
+        "init-runtime",
+        "--path",
+        "runtimes/dev",
+        "-f",
+        "pallets.toml",
+    ];
+    let output = fixture.run(&args);
+    assert_error(&output, 2, &args);
+    assert!(!fixture.path("runtimes").exists());
+    assert_eq!(fixture.read("Cargo.toml"), workspace_manifest);
+}